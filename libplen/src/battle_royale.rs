@@ -0,0 +1,360 @@
+use rand::Rng;
+use serde_derive::{Serialize, Deserialize};
+
+use crate::constants;
+use crate::gamemode::{self, GameMode, ModeContext, ModeName};
+use crate::killfeed::KillFeed;
+use crate::math::{self, Vec2, vec2};
+use crate::player::Player;
+use crate::round::RoundResult;
+use crate::scoreboard::{Scoreboard, Weapon};
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct ZonePhase {
+    // Seconds before the zone starts shrinking
+    pub wait_time: f32,
+    pub shrink_time: f32,
+    // Radius of the safe zone at the end of the phase
+    pub radius: f32,
+    // Damage per second to planes outside the zone during this phase
+    pub damage: f32,
+}
+
+impl Default for ZonePhase {
+    fn default() -> Self {
+        Self {
+            wait_time: 60.,
+            shrink_time: 30.,
+            radius: 1000.,
+            damage: 5.,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct BattleRoyaleConfig {
+    pub phases: Vec<ZonePhase>,
+    // How many more powerups there are at the start, the amount drops to
+    // normal as the zone shrinks
+    pub early_powerup_factor: f32,
+}
+
+impl Default for BattleRoyaleConfig {
+    fn default() -> Self {
+        let phase = |wait_time, shrink_time, radius, damage| {
+            ZonePhase { wait_time, shrink_time, radius, damage }
+        };
+        Self {
+            phases: vec!(
+                phase(60., 30., 1000., 5.),
+                phase(45., 30., 500., 10.),
+                phase(30., 20., 200., 20.),
+                phase(20., 20., 0., 30.),
+            ),
+            early_powerup_factor: 3.,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum ZoneState {
+    Waiting,
+    Shrinking,
+    // The last phase is over
+    Closed,
+}
+
+/**
+ * The safe zone, which shrinks towards a random point inside itself in
+ * every phase. Distances wrap around the world like everything else.
+ */
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SafeZone {
+    pub center: Vec2,
+    pub radius: f32,
+    // Where the zone ends up at the end of the current phase
+    pub target_center: Vec2,
+    pub target_radius: f32,
+    pub state: ZoneState,
+    pub time_left: f32,
+    pub phase: usize,
+    start_center: Vec2,
+    start_radius: f32,
+}
+
+impl SafeZone {
+    fn new() -> Self {
+        let center = vec2(constants::WORLD_SIZE * 0.5, constants::WORLD_SIZE * 0.5);
+        Self {
+            center,
+            radius: constants::SAFE_ZONE_START_RADIUS,
+            target_center: center,
+            target_radius: constants::SAFE_ZONE_START_RADIUS,
+            state: ZoneState::Waiting,
+            time_left: 0.,
+            phase: 0,
+            start_center: center,
+            start_radius: constants::SAFE_ZONE_START_RADIUS,
+        }
+    }
+
+    pub fn contains(&self, position: Vec2) -> bool {
+        math::wrapped_distance(self.center, position) <= self.radius
+    }
+
+    fn start_phase(&mut self, phase: usize, phases: &[ZonePhase], rng: &mut impl Rng) {
+        self.phase = phase;
+        self.start_center = self.center;
+        self.start_radius = self.radius;
+        match phases.get(phase) {
+            Some(config) => {
+                // The next zone lies completely inside the current one
+                let target_radius = config.radius.min(self.radius);
+                let offset = Vec2::from_direction(
+                    rng.gen_range(0., std::f32::consts::PI * 2.),
+                    rng.gen_range(0., self.radius - target_radius + 1.)
+                );
+                self.target_center = math::wrap_around(self.center + offset);
+                self.target_radius = target_radius;
+                self.state = ZoneState::Waiting;
+                self.time_left = config.wait_time;
+            }
+            None => {
+                self.state = ZoneState::Closed;
+                self.time_left = f32::INFINITY;
+            }
+        }
+    }
+
+    fn update(&mut self, delta: f32, phases: &[ZonePhase], rng: &mut impl Rng) {
+        self.time_left -= delta;
+        match self.state {
+            ZoneState::Waiting if self.time_left <= 0. => {
+                self.state = ZoneState::Shrinking;
+                self.time_left = phases[self.phase].shrink_time;
+            }
+            ZoneState::Shrinking => {
+                let shrink_time = phases[self.phase].shrink_time;
+                let progress = if shrink_time > 0. {
+                    (1. - self.time_left / shrink_time).min(1.)
+                } else {
+                    1.
+                };
+                let offset = math::wrapped_difference(self.start_center, self.target_center);
+                self.center = math::wrap_around(self.start_center + offset * progress);
+                self.radius = self.start_radius + (self.target_radius - self.start_radius) * progress;
+
+                if self.time_left <= 0. {
+                    self.start_phase(self.phase + 1, phases, rng);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /**
+     * Damage per second outside the zone, the last phase keeps hurting
+     * after the zone has closed
+     */
+    fn damage(&self, phases: &[ZonePhase]) -> f32 {
+        phases.get(self.phase)
+            .or(phases.last())
+            .map(|phase| phase.damage)
+            .unwrap_or(0.)
+    }
+}
+
+/**
+ * Last plane standing. Nobody respawns during a round, and the safe zone
+ * forces the remaining planes together.
+ */
+#[derive(Serialize, Deserialize, Clone)]
+pub struct BattleRoyale {
+    pub zone: SafeZone,
+    // Players in the order they were knocked out, used for the placements
+    pub eliminated: Vec<u64>,
+    // Players in the game when the match started, the ones joining later
+    // wait for the next match
+    pub participants: Vec<u64>,
+    pub active: bool,
+    phases: Vec<ZonePhase>,
+    early_powerup_factor: f32,
+    damage_timer: f32,
+}
+
+impl BattleRoyale {
+    pub fn new(config: &BattleRoyaleConfig) -> Self {
+        Self {
+            zone: SafeZone::new(),
+            eliminated: vec!(),
+            participants: vec!(),
+            active: false,
+            phases: config.phases.clone(),
+            early_powerup_factor: config.early_powerup_factor,
+            damage_timer: constants::SAFE_ZONE_DAMAGE_INTERVAL,
+        }
+    }
+
+    /**
+     * Starts a new match with a full size zone between the participants
+     */
+    pub fn start(&mut self, participants: Vec<u64>, rng: &mut impl Rng) {
+        self.zone = SafeZone::new();
+        self.zone.start_phase(0, &self.phases, rng);
+        self.eliminated.clear();
+        self.participants = participants;
+        self.active = true;
+        self.damage_timer = constants::SAFE_ZONE_DAMAGE_INTERVAL;
+    }
+
+    pub fn stop(&mut self) {
+        self.active = false;
+    }
+
+    /**
+     * Shrinks the zone and hurts everyone outside of it. Returns the ids of
+     * the players that took damage.
+     */
+    pub fn update(
+        &mut self,
+        delta: f32,
+        players: &mut [Player],
+        scoreboard: &mut Scoreboard,
+        killfeed: &mut KillFeed,
+        rng: &mut impl Rng,
+    ) -> Vec<u64> {
+        if !self.active {
+            return vec!();
+        }
+        self.zone.update(delta, &self.phases, rng);
+
+        let mut hurt_players = vec!();
+        self.damage_timer -= delta;
+        if self.damage_timer <= 0. {
+            self.damage_timer += constants::SAFE_ZONE_DAMAGE_INTERVAL;
+            let damage = (self.zone.damage(&self.phases)
+                * constants::SAFE_ZONE_DAMAGE_INTERVAL) as i16;
+
+            for player in players.iter_mut() {
+                if player.health <= 0 || self.zone.contains(player.position) {
+                    continue;
+                }
+                // The zone ignores invincibility and spawn protection
+                let health_before = player.health;
+                player.health = (player.health - damage).max(0);
+                scoreboard.record_damage(
+                    player.id, player.id, health_before - player.health, Weapon::Zone
+                );
+                hurt_players.push(player.id);
+
+                if player.has_died() {
+                    scoreboard.record_kill(player.id, player.id, Weapon::Zone);
+                    let msg = gamemode::kill_message(None, &player.name, Weapon::Zone);
+                    killfeed.add_message(&msg);
+                }
+            }
+        }
+
+        // Planes can die and be moved to the dead players before the mode
+        // sees them, so everyone in the match that isn't flying is out
+        for id in &self.participants {
+            let alive = players.iter().any(|p| p.id == *id && p.health > 0);
+            if !alive && !self.eliminated.contains(id) {
+                self.eliminated.push(*id);
+            }
+        }
+        hurt_players
+    }
+}
+
+impl GameMode for BattleRoyale {
+    fn name(&self) -> ModeName {
+        ModeName::BattleRoyale
+    }
+
+    // Players that join during a match wait for the next one
+    fn on_join(&mut self, _player: &Player) -> bool {
+        !self.active
+    }
+
+    fn can_respawn(&self) -> bool {
+        !self.active
+    }
+
+    fn on_tick(&mut self, delta: f32, ctx: &mut ModeContext) -> Vec<u64> {
+        self.update(delta, ctx.players, ctx.scoreboard, ctx.killfeed, ctx.rng)
+    }
+
+    /**
+     * Number of powerups to keep on the map, more early on so that
+     * everyone can arm themselves
+     */
+    fn powerup_amount(&self) -> usize {
+        if !self.active {
+            return constants::POWERUP_AMOUNT as usize;
+        }
+        let phases = self.phases.len().max(1) as f32;
+        let progress = (self.zone.phase as f32 / phases).min(1.);
+        let factor = 1. + (self.early_powerup_factor - 1.) * (1. - progress);
+        (constants::POWERUP_AMOUNT as f32 * factor) as usize
+    }
+
+    fn on_round_start(&mut self, ctx: &mut ModeContext) {
+        // Everyone is revived for the match, the dead players too
+        let participants = ctx.players.iter()
+            .map(|p| p.id)
+            .chain(ctx.dead_players.iter().map(|dead| dead.player.id))
+            .collect();
+        self.start(participants, ctx.rng);
+    }
+
+    // Everyone starts the match at full health
+    fn respawn_on_start(&self) -> bool {
+        true
+    }
+
+    fn on_round_end(&mut self) {
+        self.stop();
+    }
+
+    // The last plane standing wins, not the one with the most kills
+    fn best_score(&self, _scoreboard: &Scoreboard) -> u32 {
+        0
+    }
+
+    /**
+     * Ends the match when at most one plane is left
+     */
+    fn round_over(&self, ctx: &ModeContext) -> bool {
+        if !self.active {
+            return false;
+        }
+        let alive = ctx.players.iter().filter(|p| p.health > 0).count();
+        // Someone playing alone plays until the zone gets them
+        alive == 0 || (alive == 1 && self.participants.len() > 1)
+    }
+
+    /**
+     * The survivors first, then everyone else in reverse order of being
+     * knocked out. The score is the number of kills. Players who left the
+     * server have no stats any more and get no place.
+     */
+    fn results(&self, ctx: &ModeContext) -> Vec<RoundResult> {
+        let survivors = ctx.players.iter()
+            .filter(|p| p.health > 0)
+            .map(|p| p.id);
+        survivors.chain(self.eliminated.iter().rev().cloned())
+            .filter_map(|id| ctx.scoreboard.stats.get(&id))
+            .map(|stats| RoundResult { name: stats.name.clone(), score: stats.kills })
+            .collect()
+    }
+
+    fn winners(&self, ctx: &ModeContext) -> Vec<u64> {
+        ctx.players.iter()
+            .filter(|p| p.health > 0)
+            .map(|p| p.id)
+            .collect()
+    }
+}
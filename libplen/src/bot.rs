@@ -0,0 +1,609 @@
+use std::collections::BTreeMap;
+use std::f32::consts::PI;
+use std::str::FromStr;
+
+use rand::{Rng, SeedableRng};
+use rand::seq::SliceRandom;
+use serde_derive::{Serialize, Deserialize};
+
+use crate::constants;
+use crate::ctf::FlagState;
+use crate::gamemode::GameModeKind;
+use crate::gamestate::{GameRng, GameState};
+use crate::math::{self, Vec2, vec2};
+use crate::messages::ClientInput;
+use crate::player::{Color, PlaneType, Player};
+use crate::powerups::PowerUpKind;
+use crate::projectiles::ProjectileKind;
+use crate::teams;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+    // Sees invisible planes and everything off screen
+    Brutal,
+}
+
+impl Difficulty {
+    pub fn name(&self) -> &str {
+        match self {
+            Difficulty::Easy => "easy",
+            Difficulty::Normal => "normal",
+            Difficulty::Hard => "hard",
+            Difficulty::Brutal => "brutal",
+        }
+    }
+
+    // Seconds between the bot looking around for targets and missiles
+    pub fn reaction_time(&self) -> f32 {
+        match self {
+            Difficulty::Easy => 0.6,
+            Difficulty::Normal => 0.3,
+            Difficulty::Hard => 0.15,
+            Difficulty::Brutal => 0.05,
+        }
+    }
+
+    // Largest error in radians when aiming
+    pub fn aim_error(&self) -> f32 {
+        match self {
+            Difficulty::Easy => 0.3,
+            Difficulty::Normal => 0.15,
+            Difficulty::Hard => 0.06,
+            Difficulty::Brutal => 0.,
+        }
+    }
+
+    // How hard the bot pulls the stick when turning
+    pub fn turn_aggressiveness(&self) -> f32 {
+        match self {
+            Difficulty::Easy => 0.5,
+            Difficulty::Normal => 0.8,
+            Difficulty::Hard => 1.,
+            Difficulty::Brutal => 1.5,
+        }
+    }
+
+    /**
+     * Whether the bot only knows what a human would: planes on its screen
+     * and radar blips, but no invisible planes
+     */
+    pub fn is_fair(&self) -> bool {
+        *self != Difficulty::Brutal
+    }
+}
+
+impl FromStr for Difficulty {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "easy" => Ok(Difficulty::Easy),
+            "normal" => Ok(Difficulty::Normal),
+            "hard" => Ok(Difficulty::Hard),
+            "brutal" => Ok(Difficulty::Brutal),
+            _ => Err(format!("Unknown difficulty {}", s)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Profile {
+    // Chases and leads the closest enemy
+    Dogfighter,
+    // Hunts for lasers and fires them from a distance
+    Sniper,
+    // Flies straight into enemies at full speed
+    Rammer,
+    // Collects powerups and only fights when armed or cornered
+    Scavenger,
+}
+
+impl Profile {
+    pub fn name(&self) -> &str {
+        match self {
+            Profile::Dogfighter => "dogfighter",
+            Profile::Sniper => "sniper",
+            Profile::Rammer => "rammer",
+            Profile::Scavenger => "scavenger",
+        }
+    }
+}
+
+impl FromStr for Profile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dogfighter" => Ok(Profile::Dogfighter),
+            "sniper" => Ok(Profile::Sniper),
+            "rammer" => Ok(Profile::Rammer),
+            "scavenger" => Ok(Profile::Scavenger),
+            _ => Err(format!("Unknown profile {}", s)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct BotConfig {
+    // Bots fill the game up to this many players, one leaves for every
+    // human that joins
+    pub count: usize,
+    pub difficulty: Difficulty,
+    // Handed out to the bots in turn
+    pub profiles: Vec<Profile>,
+}
+
+impl Default for BotConfig {
+    fn default() -> Self {
+        Self {
+            count: 0,
+            difficulty: Difficulty::Normal,
+            profiles: vec!(
+                Profile::Dogfighter,
+                Profile::Sniper,
+                Profile::Rammer,
+                Profile::Scavenger,
+            ),
+        }
+    }
+}
+
+const NAMES: [&str; 8] = [
+    "Red Baron", "Biggles", "Maverick", "Snoopy", "Iceman", "Goose", "Sky King", "Porco",
+];
+
+const PLANES: [PlaneType; 4] = [
+    PlaneType::SukaBlyat,
+    PlaneType::HowdyCowboy,
+    PlaneType::ElPolloRomero,
+    PlaneType::AchtungBlitzKrieg,
+];
+
+/**
+ * A missile closing in on the bot
+ */
+#[derive(Clone, Copy)]
+struct Threat {
+    // Direction the missile is flying in
+    direction: Vec2,
+    // From the missile to the bot
+    offset: Vec2,
+}
+
+/**
+ * A plane the radar picked up, which fades out like on the minimap
+ */
+struct Blip {
+    position: Vec2,
+    lifetime: f32,
+}
+
+/**
+ * A player controlled by the server. Every tick the bot looks at the game
+ * state and comes up with the input a human would have sent.
+ */
+pub struct Bot {
+    pub id: u64,
+    pub difficulty: Difficulty,
+    pub profile: Profile,
+    // What the bot decided the last time it looked around, kept until the
+    // reaction time has passed
+    target: Option<u64>,
+    threat: Option<Threat>,
+    aim_error: f32,
+    reaction_timer: f32,
+    radar_angle: f32,
+    // Ordered by id so that ties are broken the same way every run
+    blips: BTreeMap<u64, Blip>,
+    // Where the bot flies when there is nothing else to do
+    wander_point: Vec2,
+    wander_time: f32,
+    // Derived from the game seed, so that bots decide the same way every
+    // time a seed is played
+    rng: GameRng,
+}
+
+impl Bot {
+    pub fn new(id: u64, difficulty: Difficulty, profile: Profile, seed: u64) -> Self {
+        Self {
+            id,
+            difficulty,
+            profile,
+            target: None,
+            threat: None,
+            aim_error: 0.,
+            reaction_timer: 0.,
+            radar_angle: 0.,
+            blips: BTreeMap::new(),
+            wander_point: vec2(0., 0.),
+            wander_time: 0.,
+            // Every bot gets its own stream of numbers
+            rng: GameRng::seed_from_u64(seed ^ id.wrapping_mul(0x9e37_79b9_7f4a_7c15)),
+        }
+    }
+
+    /**
+     * Picks the name, plane and color of the bot, with a name that isn't
+     * taken yet
+     */
+    pub fn pick_identity(&mut self, state: &GameState) -> (String, PlaneType, Color) {
+        let rng = &mut self.rng;
+        let taken = |name: &str| {
+            state.players.iter()
+                .chain(state.dead_players.iter().map(|dead| &dead.player))
+                .any(|p| p.name == name)
+        };
+        let name = NAMES.iter()
+            .map(|name| format!("{} [bot]", name))
+            .find(|name| !taken(name))
+            .unwrap_or(format!("Bot {}", self.id));
+        let color = match rng.gen_range(0, 5) {
+            0 => Color::Red,
+            1 => Color::Green,
+            2 => Color::Blue,
+            3 => Color::Yellow,
+            _ => Color::Purple,
+        };
+        let plane = *PLANES.choose(rng).unwrap();
+        (name, plane, color)
+    }
+
+    pub fn update(&mut self, state: &GameState, delta: f32) -> ClientInput {
+        let mut input = ClientInput::new();
+        let me = match state.get_player_by_id(self.id) {
+            Some(me) if me.health > 0 => me,
+            _ => return input,
+        };
+
+        self.sweep_radar(state, me, delta);
+        self.reaction_timer -= delta;
+        if self.reaction_timer <= 0. {
+            self.reaction_timer = self.difficulty.reaction_time();
+            self.target = self.pick_target(state, me);
+            self.threat = incoming_missile(state, me);
+            let max_error = self.difficulty.aim_error();
+            self.aim_error = self.rng.gen_range(-max_error, max_error + 0.0001);
+        }
+
+        let target = self.target
+            .and_then(|id| state.get_player_by_id(id))
+            .filter(|target| target.health > 0);
+        let target_distance = target
+            .map(|t| math::wrapped_distance(me.position, t.position))
+            .unwrap_or(f32::INFINITY);
+
+        let aim_point = target.map(|target| match self.profile {
+            // Rammers fly to where the target will be when they get there
+            Profile::Rammer => {
+                let time = target_distance / me.velocity().norm().max(1.);
+                math::wrap_around(target.position + target.velocity() * time)
+            }
+            _ => lead_target(me, target),
+        });
+        let aim_direction = aim_point.map(|aim_point| {
+            let direction = math::wrapped_difference(me.position, aim_point);
+            Vec2::from_direction(direction.angle() + self.aim_error, direction.norm())
+        });
+
+        let mut direction = if let Some(threat) = &self.threat {
+            // Break away to the side the missile is not on
+            let side = vec2(-threat.direction.y, threat.direction.x);
+            if side.dot(threat.offset) >= 0. {side} else {-side}
+        } else if let Some(powerup) = self.wanted_powerup(state, me, target_distance) {
+            math::wrapped_difference(me.position, powerup)
+        } else if let Some(aim_direction) = aim_direction {
+            if target_distance < self.keep_distance(me) {
+                -aim_direction
+            } else {
+                aim_direction
+            }
+        } else if let Some(objective) = objective(state, me) {
+            math::wrapped_difference(me.position, objective)
+        } else if let Some(position) = self.hunt(state, me) {
+            math::wrapped_difference(me.position, position)
+        } else if let Some(powerup) = nearest_powerup(state, me, None) {
+            math::wrapped_difference(me.position, powerup)
+        } else {
+            self.wander(me, delta)
+        };
+        direction = avoid_hazards(state, me, direction);
+
+        // The nose of the plane points a quarter turn from its rotation
+        let wanted_rotation = direction.angle() + PI / 2.;
+        let turn = math::angle_diff(me.rotation, wanted_rotation);
+        let gain = constants::BOT_TURN_GAIN * self.difficulty.turn_aggressiveness();
+        input.x_input = (turn * gain).clamp(-1., 1.);
+        // Slow down for sharp turns and to not ram the target, unless that
+        // is the plan
+        let too_close = target_distance < constants::BOT_MIN_DISTANCE &&
+            self.profile != Profile::Rammer;
+        input.y_input = if turn.abs() > PI / 2. || too_close {-1.} else {1.};
+
+        if let Some(aim_direction) = aim_direction {
+            let aim = math::angle_diff(me.rotation - PI / 2., aim_direction.angle());
+            input.shooting = can_hit(me, target_distance, aim.abs());
+        }
+
+        if let Some(powerup) = me.available_powerup {
+            let threatened = self.threat.is_some();
+            let hurt = me.health < me.max_health() / 2;
+            let chasing = match self.profile {
+                Profile::Rammer => target_distance < constants::BOT_CHASE_DISTANCE,
+                _ => target_distance > constants::BOT_CHASE_DISTANCE,
+            };
+            input.activating_powerup = match powerup {
+                PowerUpKind::Afterburner => threatened || chasing,
+                PowerUpKind::Invincibility => threatened || (hurt && target.is_some()),
+                PowerUpKind::SlowTime => threatened || target_distance < constants::BOT_MIN_DISTANCE * 2.,
+                PowerUpKind::Invisible => !me.has_flag && (threatened || hurt),
+                _ => false,
+            };
+        }
+
+        input
+    }
+
+    /**
+     * Whether an enemy plane can be seen right now
+     */
+    fn can_see(&self, me: &Player, other: &Player) -> bool {
+        let distance = math::wrapped_distance(me.position, other.position);
+        if self.difficulty.is_fair() {
+            !other.is_invisible() && distance < constants::BOT_VIEW_RANGE
+        } else {
+            distance < constants::BOT_SIGHT_RANGE
+        }
+    }
+
+    /**
+     * The closest enemy in sight, the current target is preferred so the
+     * bot doesn't keep switching between planes at the same distance
+     */
+    fn pick_target(&self, state: &GameState, me: &Player) -> Option<u64> {
+        if !state.weapons_enabled() {
+            return None;
+        }
+        state.players.iter()
+            .filter(|p| {
+                p.id != me.id &&
+                    p.health > 0 &&
+                    !teams::teammates(me.team, p.team) &&
+                    self.can_see(me, p)
+            })
+            .map(|p| {
+                let mut distance = math::wrapped_distance(me.position, p.position);
+                if Some(p.id) == self.target {
+                    distance *= constants::BOT_TARGET_STICKINESS;
+                }
+                (p.id, distance)
+            })
+            .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
+            .map(|(id, _)| id)
+    }
+
+    /**
+     * Turns the radar like the minimap of a human player does, planes are
+     * only noticed when the sweep passes over them
+     */
+    fn sweep_radar(&mut self, state: &GameState, me: &Player, delta: f32) {
+        let old_angle = self.radar_angle;
+        let angle = (old_angle + delta * constants::RADAR_SPEED) % (PI * 2.);
+        self.radar_angle = angle;
+
+        for blip in self.blips.values_mut() {
+            blip.lifetime -= delta;
+        }
+        self.blips.retain(|_, blip| blip.lifetime > 0.);
+
+        for player in &state.players {
+            if player.id == me.id || player.is_invisible() || teams::teammates(me.team, player.team) {
+                continue;
+            }
+            let offset = math::wrapped_difference(me.position, player.position);
+            if offset.norm() > constants::WORLD_SIZE / 2. {
+                continue;
+            }
+            let blip_angle = math::modulo(offset.angle(), PI * 2.);
+            let swept = if angle >= old_angle {
+                old_angle <= blip_angle && blip_angle <= angle
+            } else {
+                old_angle <= blip_angle || blip_angle <= angle
+            };
+            if swept {
+                self.blips.insert(player.id, Blip {
+                    position: player.position,
+                    lifetime: constants::RADAR_FADEOUT_TIME,
+                });
+            }
+        }
+    }
+
+    /**
+     * Where to look for enemies when none are in sight. Fair bots go by the
+     * radar, the others know where everyone is.
+     */
+    fn hunt(&self, state: &GameState, me: &Player) -> Option<Vec2> {
+        if !state.weapons_enabled() {
+            return None;
+        }
+        let positions: Vec<Vec2> = if self.difficulty.is_fair() {
+            self.blips.values().map(|blip| blip.position).collect()
+        } else {
+            state.players.iter()
+                .filter(|p| p.id != me.id && p.health > 0 && !teams::teammates(me.team, p.team))
+                .map(|p| p.position)
+                .collect()
+        };
+        closest(me.position, positions.into_iter())
+    }
+
+    /**
+     * A powerup the profile wants more than fighting
+     */
+    fn wanted_powerup(&self, state: &GameState, me: &Player, target_distance: f32) -> Option<Vec2> {
+        match self.profile {
+            Profile::Sniper if !me.has_powerup(PowerUpKind::Laser) => {
+                nearest_powerup(state, me, Some(PowerUpKind::Laser))
+            }
+            Profile::Scavenger => {
+                let armed = me.has_powerup(PowerUpKind::Laser) || me.has_powerup(PowerUpKind::Missile);
+                let cornered = target_distance < constants::BOT_MIN_DISTANCE * 3.;
+                if armed || cornered {
+                    None
+                } else {
+                    nearest_powerup(state, me, None)
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /**
+     * Snipers back off from targets that get closer than this
+     */
+    fn keep_distance(&self, me: &Player) -> f32 {
+        match self.profile {
+            Profile::Sniper if me.has_powerup(PowerUpKind::Laser) => constants::LASER_RANGE * 0.5,
+            _ => 0.,
+        }
+    }
+
+    fn wander(&mut self, me: &Player, delta: f32) -> Vec2 {
+        self.wander_time -= delta;
+        let arrived = math::wrapped_distance(me.position, self.wander_point) < 100.;
+        if self.wander_time <= 0. || arrived {
+            self.wander_point = vec2(
+                self.rng.gen_range(0., constants::WORLD_SIZE),
+                self.rng.gen_range(0., constants::WORLD_SIZE)
+            );
+            self.wander_time = constants::BOT_WANDER_TIME;
+        }
+        math::wrapped_difference(me.position, self.wander_point)
+    }
+}
+
+/**
+ * Where to aim so that a bullet meets the target, bullets keep the velocity
+ * of the plane that fired them
+ */
+fn lead_target(me: &Player, target: &Player) -> Vec2 {
+    let relative_velocity = target.velocity() - me.velocity();
+    let mut aim_point = target.position;
+    // A couple of rounds is close enough, the target turns anyway
+    for _ in 0..3 {
+        let time = math::wrapped_distance(me.position, aim_point) / constants::BULLET_VELOCITY;
+        aim_point = math::wrap_around(target.position + relative_velocity * time);
+    }
+    aim_point
+}
+
+/**
+ * Whether the weapon of the plane hits something at a distance and an
+ * angle off the nose
+ */
+fn can_hit(me: &Player, distance: f32, angle: f32) -> bool {
+    if me.has_powerup(PowerUpKind::Laser) {
+        distance < constants::LASER_RANGE && angle < constants::BOT_AIM_TOLERANCE
+    } else if me.has_powerup(PowerUpKind::Missile) {
+        distance < constants::BOT_SIGHT_RANGE && angle < constants::MISSILE_LOCK_ANGLE / 2.
+    } else {
+        distance < constants::BULLET_MAX_TRAVEL && angle < constants::BOT_AIM_TOLERANCE
+    }
+}
+
+/**
+ * The closest enemy missile flying towards the bot
+ */
+fn incoming_missile(state: &GameState, me: &Player) -> Option<Threat> {
+    state.projectiles.iter()
+        .filter_map(|projectile| match projectile {
+            ProjectileKind::Missile(missile) => Some(missile),
+            ProjectileKind::Bullet(_) => None,
+        })
+        .filter(|missile| missile.owner != me.id && !teams::teammates(missile.team, me.team))
+        .map(|missile| Threat {
+            direction: Vec2::from_direction(missile.angle, 1.),
+            offset: math::wrapped_difference(missile.position, me.position),
+        })
+        .filter(|threat| {
+            let distance = threat.offset.norm();
+            distance < constants::BOT_DODGE_DISTANCE &&
+                threat.direction.dot(threat.offset / distance.max(1.)) > 0.5
+        })
+        .min_by(|a, b| a.offset.norm().partial_cmp(&b.offset.norm()).unwrap())
+}
+
+/**
+ * Where the game mode wants the bot to be, if anywhere
+ */
+fn objective(state: &GameState, me: &Player) -> Option<Vec2> {
+    match &state.mode {
+        GameModeKind::Race(race) => {
+            let next = race.racers.get(&me.id).map(|r| r.next_checkpoint).unwrap_or(0);
+            race.checkpoints.get(next).cloned()
+        }
+        GameModeKind::KingOfTheHill(koth) => {
+            closest(me.position, koth.zones.iter().map(|zone| zone.position))
+        }
+        GameModeKind::CaptureTheFlag(ctf) => {
+            let own_flag = ctf.flags.iter().find(|f| Some(f.team) == me.team)?;
+            if me.has_flag {
+                Some(own_flag.base)
+            } else if let FlagState::Dropped {..} = own_flag.state {
+                Some(own_flag.position)
+            } else {
+                ctf.flags.iter()
+                    .find(|f| Some(f.team) != me.team && f.carrier().is_none())
+                    .map(|f| f.position)
+            }
+        }
+        GameModeKind::BattleRoyale(battle_royale) if battle_royale.active => {
+            Some(battle_royale.zone.target_center)
+        }
+        _ => None,
+    }
+}
+
+fn nearest_powerup(state: &GameState, me: &Player, kind: Option<PowerUpKind>) -> Option<Vec2> {
+    let powerups = state.powerups.iter()
+        .filter(|powerup| kind.map(|kind| powerup.kind == kind).unwrap_or(true))
+        .map(|powerup| powerup.position);
+    closest(me.position, powerups)
+}
+
+fn closest(from: Vec2, positions: impl Iterator<Item = Vec2>) -> Option<Vec2> {
+    positions.min_by(|a, b| {
+        math::wrapped_distance(from, *a)
+            .partial_cmp(&math::wrapped_distance(from, *b))
+            .unwrap()
+    })
+}
+
+/**
+ * Bends the direction away from the hurricane, and back into the safe zone
+ * of a battle royale
+ */
+fn avoid_hazards(state: &GameState, me: &Player, direction: Vec2) -> Vec2 {
+    let mut direction = direction / direction.norm().max(0.001);
+    if let Some(hurricane) = &state.hurricane {
+        let away = math::wrapped_difference(hurricane.position, me.position);
+        let radius = hurricane.size() / 2. + constants::BOT_HURRICANE_MARGIN;
+        let distance = away.norm();
+        if distance < radius {
+            direction += away / distance.max(1.) * (1. - distance / radius) * 3.;
+        }
+    }
+    if let GameModeKind::BattleRoyale(battle_royale) = &state.mode {
+        let zone = &battle_royale.zone;
+        if battle_royale.active && !zone.contains(me.position) {
+            direction = math::wrapped_difference(me.position, zone.center);
+        }
+    }
+    direction
+}
@@ -11,6 +11,8 @@ pub const POWERUP_BOUNCE_HEIGHT: f32 = 10.;
 pub const WORLD_SIZE: f32 = 3000.;
 pub const DELTA_TIME: f32 = 0.01;
 pub const SERVER_SLEEP_DURATION: u64 = 10;
+// Milliseconds a server can fall behind before it stops catching up
+pub const SERVER_MAX_TICK_BACKLOG: u64 = 250;
 
 pub const MAX_SPEED: f32 = 400.;
 pub const MIN_SPEED: f32 = 50.;
@@ -67,10 +69,54 @@ pub const COLOR_SELECTION_SIZE: f32 = 200.;
 
 pub const NAME_POS: (f32, f32) = (50., 150.);
 
+pub const SPECTATOR_CAMERA_SPEED: f32 = 800.;
+
 pub const SMOKE_SPAWN_RATE: f32 = 0.05;
 pub const SPARK_SPAWN_RATE: f32 = 0.01;
 pub const SPARK_SPREAD: f32 = 30.;
 
+pub const RESPAWN_DELAY: f32 = 5.;
+pub const SPAWN_PROTECTION_TIME: f32 = 3.;
+pub const SPAWN_PROTECTION_BLINK_SPEED: f32 = 10.;
+// Number of random points considered when looking for a safe spawn point
+pub const SPAWN_CANDIDATES: usize = 32;
+// How much more dangerous projectiles and lasers are than planes when spawning
+pub const SPAWN_PROJECTILE_WEIGHT: f32 = 2.;
+pub const SPAWN_LASER_WEIGHT: f32 = 3.;
+// Scales the safety of points inside the hurricane
+pub const SPAWN_HURRICANE_FACTOR: f32 = 0.1;
+
+// Damage multiplier for teammates when friendly fire is reduced
+pub const FRIENDLY_FIRE_REDUCTION: f32 = 0.5;
+
+pub const FLAG_RADIUS: u32 = 20;
+pub const BASE_RADIUS: f32 = 120.;
+
+// Large enough to cover the whole world
+pub const SAFE_ZONE_START_RADIUS: f32 = 2200.;
+pub const SAFE_ZONE_DAMAGE_INTERVAL: f32 = 1.;
+
+// Time between the recorded positions of a race ghost
+pub const GHOST_FRAME_TIME: f32 = 0.1;
+
+// How hard bots turn towards where they want to go, 1 turns fully at one
+// radian off
+pub const BOT_TURN_GAIN: f32 = 3.;
+// Bots shoot when they aim this close to the target, in radians
+pub const BOT_AIM_TOLERANCE: f32 = 0.12;
+pub const BOT_SIGHT_RANGE: f32 = 900.;
+// How far fair bots can see, about what fits on the screen of a human
+pub const BOT_VIEW_RANGE: f32 = WINDOW_SIZE * 0.6;
+// Bots slow down to not ram targets closer than this
+pub const BOT_MIN_DISTANCE: f32 = 120.;
+// Bots use the afterburner on targets further away than this
+pub const BOT_CHASE_DISTANCE: f32 = 600.;
+pub const BOT_DODGE_DISTANCE: f32 = 400.;
+pub const BOT_HURRICANE_MARGIN: f32 = 150.;
+// The current target counts as this much closer when picking a new one
+pub const BOT_TARGET_STICKINESS: f32 = 0.7;
+pub const BOT_WANDER_TIME: f32 = 10.;
+
 pub const COLLISION_DAMAGE: i16 = 40;
 pub const COLLISION_GRACE_PERIOD: f32 = 1.;
 
@@ -82,6 +128,27 @@ pub const HIT_SHAKE_MAGNITUDE: f32 = 7.;
 
 pub const KILLFEED_DURATION: f32 = 4.;
 
+// Longest chat message in characters, longer ones are cut off
+pub const CHAT_MAX_LENGTH: usize = 150;
+// A player can send this many chat messages per rate window
+pub const CHAT_RATE_LIMIT: usize = 5;
+pub const CHAT_RATE_WINDOW: u64 = 10;
+// Seconds a chat message is shown, the last of them fading out
+pub const CHAT_DURATION: f32 = 10.;
+pub const CHAT_FADE_TIME: f32 = 2.;
+
+pub const KILL_SCORE: u32 = 2;
+pub const CAPTURE_SCORE: u32 = 5;
+pub const ASSIST_SCORE: u32 = 1;
+// Damage done this long before a kill gives an assist, in seconds
+pub const ASSIST_TIME: f32 = 5.;
+
+// Number of players in the lifetime stats top list
+pub const LIFETIME_STATS_COUNT: usize = 10;
+
+// Time the game is frozen at the end of a round before the intermission
+pub const ROUND_END_DURATION: f32 = 3.;
+
 pub const HURRICANE_PROBABILITY: f32 = 0.005;
 pub const HURRICANE_MOVE_SPEED: f32 = 30.;
 pub const HURRICANE_GROW_SPEED: f32 = 0.1;
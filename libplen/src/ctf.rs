@@ -0,0 +1,221 @@
+use serde_derive::{Serialize, Deserialize};
+
+use crate::constants::{self, PLANE_SIZE};
+use crate::gamemode::{GameMode, ModeContext, ModeName};
+use crate::killfeed::KillFeed;
+use crate::math::{Vec2, vec2, wrapped_distance};
+use crate::player::Player;
+use crate::scoreboard::Scoreboard;
+use crate::teams::Team;
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct CtfConfig {
+    pub red_base: (f32, f32),
+    pub blue_base: (f32, f32),
+    // Seconds before a dropped flag goes back to its base by itself
+    pub return_time: f32,
+}
+
+impl Default for CtfConfig {
+    fn default() -> Self {
+        Self {
+            red_base: (constants::WORLD_SIZE * 0.2, constants::WORLD_SIZE * 0.5),
+            blue_base: (constants::WORLD_SIZE * 0.8, constants::WORLD_SIZE * 0.5),
+            return_time: 20.,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum FlagState {
+    AtBase,
+    Carried { carrier: u64 },
+    Dropped { return_time: f32 },
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Flag {
+    pub team: Team,
+    pub base: Vec2,
+    pub position: Vec2,
+    pub state: FlagState,
+}
+
+impl Flag {
+    fn new(team: Team, base: Vec2) -> Self {
+        Self {
+            team,
+            base,
+            position: base,
+            state: FlagState::AtBase,
+        }
+    }
+
+    fn return_home(&mut self) {
+        self.position = self.base;
+        self.state = FlagState::AtBase;
+    }
+
+    pub fn carrier(&self) -> Option<u64> {
+        match self.state {
+            FlagState::Carried { carrier } => Some(carrier),
+            _ => None,
+        }
+    }
+}
+
+/**
+ * Capture the flag. Every team has a flag at its base, which the other team
+ * scores with by flying it to their own base while their own flag is home.
+ */
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CaptureTheFlag {
+    pub flags: Vec<Flag>,
+    return_time: f32,
+}
+
+impl CaptureTheFlag {
+    pub fn new(config: &CtfConfig) -> Self {
+        let (red_x, red_y) = config.red_base;
+        let (blue_x, blue_y) = config.blue_base;
+        Self {
+            flags: vec!(
+                Flag::new(Team::Red, vec2(red_x, red_y)),
+                Flag::new(Team::Blue, vec2(blue_x, blue_y)),
+            ),
+            return_time: config.return_time,
+        }
+    }
+
+    /**
+     * Puts all flags back at their bases
+     */
+    pub fn reset(&mut self, players: &mut [Player]) {
+        for flag in &mut self.flags {
+            flag.return_home();
+        }
+        for player in players {
+            player.has_flag = false;
+        }
+    }
+
+    pub fn update(
+        &mut self,
+        delta: f32,
+        players: &mut [Player],
+        scoreboard: &mut Scoreboard,
+        killfeed: &mut KillFeed,
+    ) {
+        self.update_flags(delta, players, killfeed);
+
+        let pickup_radius = (PLANE_SIZE + constants::FLAG_RADIUS) as f32;
+        for player in players.iter_mut() {
+            let team = match player.team {
+                Some(team) if player.health > 0 => team,
+                _ => continue,
+            };
+
+            for i in 0..self.flags.len() {
+                if wrapped_distance(self.flags[i].position, player.position) > pickup_radius {
+                    continue;
+                }
+
+                let flag = &mut self.flags[i];
+                match flag.state {
+                    FlagState::AtBase | FlagState::Dropped {..} if flag.team != team => {
+                        flag.state = FlagState::Carried { carrier: player.id };
+                        player.has_flag = true;
+                        let msg = format!("{} took the {} flag!", player.name, flag.team.name());
+                        killfeed.add_message(&msg);
+                    }
+                    FlagState::Dropped {..} => {
+                        flag.return_home();
+                        let msg = format!("{} returned the {} flag.", player.name, team.name());
+                        killfeed.add_message(&msg);
+                    }
+                    FlagState::AtBase => {
+                        // Touching the home flag while carrying the enemy flag
+                        // captures it
+                        let carried = self.flags.iter_mut()
+                            .find(|f| f.carrier() == Some(player.id));
+                        if let Some(enemy_flag) = carried {
+                            enemy_flag.return_home();
+                            player.has_flag = false;
+                            scoreboard.record_capture(player.id);
+                            let msg = format!(
+                                "{} captured the {} flag!",
+                                player.name,
+                                enemy_flag.team.name()
+                            );
+                            killfeed.add_message(&msg);
+                        }
+                    }
+                    FlagState::Carried {..} => {}
+                }
+            }
+        }
+    }
+
+    /**
+     * Moves carried flags with their carriers, drops the flags of carriers
+     * that died and returns flags that have been lying around for too long
+     */
+    fn update_flags(&mut self, delta: f32, players: &mut [Player], killfeed: &mut KillFeed) {
+        for flag in &mut self.flags {
+            match flag.state {
+                FlagState::Carried { carrier } => {
+                    match players.iter().find(|p| p.id == carrier && p.health > 0) {
+                        Some(player) => flag.position = player.position,
+                        None => {
+                            flag.state = FlagState::Dropped { return_time: self.return_time };
+                            let msg = format!("The {} flag was dropped.", flag.team.name());
+                            killfeed.add_message(&msg);
+                        }
+                    }
+                }
+                FlagState::Dropped { return_time } if return_time <= delta => {
+                    flag.return_home();
+                    let msg = format!("The {} flag returned to its base.", flag.team.name());
+                    killfeed.add_message(&msg);
+                }
+                FlagState::Dropped { return_time } => {
+                    flag.state = FlagState::Dropped { return_time: return_time - delta };
+                }
+                FlagState::AtBase => {}
+            }
+        }
+
+        for player in players {
+            player.has_flag = self.flags.iter().any(|f| f.carrier() == Some(player.id));
+        }
+    }
+}
+
+impl GameMode for CaptureTheFlag {
+    fn name(&self) -> ModeName {
+        ModeName::CaptureTheFlag
+    }
+
+    fn requires_teams(&self) -> bool {
+        true
+    }
+
+    // Only captures give team score
+    fn kills_count_for_team(&self) -> bool {
+        false
+    }
+
+    fn on_tick(&mut self, delta: f32, ctx: &mut ModeContext) -> Vec<u64> {
+        self.update(delta, ctx.players, ctx.scoreboard, ctx.killfeed);
+        vec!()
+    }
+
+    fn on_warmup(&mut self, ctx: &mut ModeContext) {
+        self.reset(ctx.players);
+    }
+
+    fn on_round_start(&mut self, ctx: &mut ModeContext) {
+        self.reset(ctx.players);
+    }
+}
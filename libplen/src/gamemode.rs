@@ -0,0 +1,216 @@
+use std::str::FromStr;
+
+use enum_dispatch::enum_dispatch;
+use serde_derive::{Serialize, Deserialize};
+
+use crate::constants;
+use crate::battle_royale::BattleRoyale;
+use crate::ctf::CaptureTheFlag;
+use crate::gamestate::GameRng;
+use crate::killfeed::KillFeed;
+use crate::koth::KingOfTheHill;
+use crate::player::{DeadPlayer, Player};
+use crate::powerups::PowerUpKind;
+use crate::race::Race;
+use crate::round::RoundResult;
+use crate::scoreboard::{Scoreboard, Weapon};
+use crate::teams::TeamConfig;
+
+/**
+ * The modes a server can be configured to play
+ */
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+pub enum ModeName {
+    #[serde(rename = "ffa")]
+    #[default]
+    FreeForAll,
+    #[serde(rename = "ctf")]
+    CaptureTheFlag,
+    #[serde(rename = "koth")]
+    KingOfTheHill,
+    #[serde(rename = "battle_royale")]
+    BattleRoyale,
+    #[serde(rename = "race")]
+    Race,
+}
+
+impl FromStr for ModeName {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ffa" => Ok(ModeName::FreeForAll),
+            "ctf" => Ok(ModeName::CaptureTheFlag),
+            "koth" => Ok(ModeName::KingOfTheHill),
+            "battle_royale" => Ok(ModeName::BattleRoyale),
+            "race" => Ok(ModeName::Race),
+            _ => Err(format!("Unknown mode {}, try ffa, ctf, koth, battle_royale or race", s)),
+        }
+    }
+}
+
+impl ModeName {
+    pub fn name(&self) -> &str {
+        match self {
+            ModeName::FreeForAll => "Free for all",
+            ModeName::CaptureTheFlag => "Capture the flag",
+            ModeName::KingOfTheHill => "King of the hill",
+            ModeName::BattleRoyale => "Battle royale",
+            ModeName::Race => "Air race",
+        }
+    }
+}
+
+/**
+ * The parts of the game state a mode can look at and change. They are
+ * borrowed next to the mode itself, see `GameState::split_mode`.
+ */
+pub struct ModeContext<'a> {
+    pub players: &'a mut Vec<Player>,
+    pub dead_players: &'a [DeadPlayer],
+    pub scoreboard: &'a mut Scoreboard,
+    pub killfeed: &'a mut KillFeed,
+    pub teams: &'a TeamConfig,
+    pub rng: &'a mut GameRng,
+}
+
+#[enum_dispatch]
+#[derive(Serialize, Deserialize, Clone)]
+pub enum GameModeKind {
+    FreeForAll,
+    CaptureTheFlag,
+    KingOfTheHill,
+    BattleRoyale,
+    Race,
+}
+
+/**
+ * The rules of a game mode. Every hook has a default that plays free for
+ * all, so a mode only implements what it does differently.
+ */
+#[enum_dispatch(GameModeKind)]
+pub trait GameMode {
+    fn name(&self) -> ModeName;
+
+    // Modes that are always played in teams
+    fn requires_teams(&self) -> bool { false }
+    // Whether kills add to the team score in team games
+    fn kills_count_for_team(&self) -> bool { true }
+
+    /**
+     * Called when a player joins. Returns false if the player has to wait
+     * for the next round.
+     */
+    fn on_join(&mut self, _player: &Player) -> bool { true }
+    fn on_leave(&mut self, _id: u64) {}
+    // Whether dead players can respawn right now
+    fn can_respawn(&self) -> bool { true }
+    fn on_spawn(&mut self, _player: &mut Player) {}
+
+    /**
+     * Called before damage is dealt, after friendly fire has been applied.
+     * Returns the damage to deal, or None to cancel the hit.
+     */
+    fn on_damage(
+        &self, _attacker: u64, _victim: &Player, damage: i16, _weapon: Weapon
+    ) -> Option<i16> {
+        Some(damage)
+    }
+
+    /**
+     * Called when a player dies, the killer is None for suicides. Returns
+     * the message for the killfeed.
+     */
+    fn on_kill(&mut self, killer: Option<&str>, victim: &Player, weapon: Weapon) -> String {
+        kill_message(killer, &victim.name, weapon)
+    }
+
+    /**
+     * Runs the rules of the mode while combat is enabled. Returns the ids
+     * of the players that took damage.
+     */
+    fn on_tick(&mut self, _delta: f32, _ctx: &mut ModeContext) -> Vec<u64> { vec!() }
+
+    /**
+     * Returns false to leave the powerup on the map
+     */
+    fn on_powerup_pickup(&mut self, _player: &Player, _kind: PowerUpKind) -> bool { true }
+    fn powerup_amount(&self) -> usize { constants::POWERUP_AMOUNT as usize }
+    fn weapons_enabled(&self) -> bool { true }
+
+    fn on_warmup(&mut self, _ctx: &mut ModeContext) {}
+    fn on_round_start(&mut self, _ctx: &mut ModeContext) {}
+    // Whether everyone is put back at full health when the round starts
+    fn respawn_on_start(&self) -> bool { false }
+    fn on_round_end(&mut self) {}
+
+    /**
+     * The score compared against the score limit, modes with their own end
+     * condition can return 0 to ignore it
+     */
+    fn best_score(&self, scoreboard: &Scoreboard) -> u32 {
+        scoreboard.best_score()
+    }
+
+    /**
+     * Whether the round should end right now, checked every tick
+     */
+    fn round_over(&self, _ctx: &ModeContext) -> bool { false }
+
+    /**
+     * Final standings of a round, best first
+     */
+    fn results(&self, ctx: &ModeContext) -> Vec<RoundResult> {
+        if ctx.teams.enabled {
+            ctx.scoreboard.sorted_teams().iter()
+                .map(|(team, score)| RoundResult {
+                    name: format!("{} team", team.name()),
+                    score: *score
+                })
+                .collect()
+        } else {
+            ctx.scoreboard.sorted().iter()
+                .map(|(_, stats)| RoundResult { name: stats.name.clone(), score: stats.score() })
+                .collect()
+        }
+    }
+
+    /**
+     * Ids of the players that won the round
+     */
+    fn winners(&self, ctx: &ModeContext) -> Vec<u64> {
+        match ctx.scoreboard.winning_team() {
+            Some(team) => ctx.scoreboard.stats.iter()
+                .filter(|(_, stats)| stats.team == Some(team))
+                .map(|(id, _)| *id)
+                .collect(),
+            None => ctx.scoreboard.sorted().first()
+                .map(|(id, _)| vec!(*id))
+                .unwrap_or(vec!()),
+        }
+    }
+}
+
+/**
+ * The default killfeed message for a death
+ */
+pub fn kill_message(killer: Option<&str>, victim: &str, weapon: Weapon) -> String {
+    match (killer, weapon) {
+        (None, Weapon::Zone) => format!("{} was caught outside the zone.", victim),
+        (None, weapon) => format!("{} killed themselves using a {}.", victim, weapon.name()),
+        (Some(killer), Weapon::Collision) => format!("{} killed {} by collision.", killer, victim),
+        (Some(killer), weapon) => format!("{} killed {} using a {}.", killer, victim, weapon.name()),
+    }
+}
+
+/**
+ * Everyone against everyone, or team deathmatch when teams are enabled
+ */
+#[derive(Serialize, Deserialize, Clone)]
+pub struct FreeForAll;
+
+impl GameMode for FreeForAll {
+    fn name(&self) -> ModeName {
+        ModeName::FreeForAll
+    }
+}
@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::sync::mpsc::Receiver;
 
 use serde_derive::{Serialize, Deserialize};
@@ -5,39 +6,130 @@ use strum::IntoEnumIterator;
 use rand::prelude::*;
 use rand::Rng;
 use rand::distributions::WeightedIndex;
+use rand_pcg::Pcg32;
 
 use crate::constants::{self, PLANE_SIZE, POWERUP_RADIUS, BULLET_RADIUS};
-use crate::player::Player;
+use crate::player::{Color, DeadPlayer, Player, PlaneType};
 use crate::projectiles::LaserBeam;
 use crate::powerups::{PowerUpKind, PowerUp};
 use crate::killfeed::KillFeed;
+use crate::messages::{ClientInput, SoundEffect};
 use crate::hurricane::Hurricane;
 use crate::math::{Vec2, vec2, wrap_around};
 use crate::projectiles::{ProjectileKind, Projectile};
 use crate::debug::DebugLine;
+use crate::gamemode::{FreeForAll, GameMode, GameModeKind, ModeContext};
+use crate::spawn;
+use crate::round::{Round, RoundConfig, RoundPhase};
+use crate::scoreboard::{KillEvent, Scoreboard, Weapon};
+use crate::teams::{self, Team, TeamConfig, TEAMS};
+
+/**
+ * Everything random in the simulation comes from this generator, so the
+ * same seed and inputs always play out the same way
+ */
+pub type GameRng = Pcg32;
+
+/**
+ * What happened during an update, see GameState::update
+ */
+pub type UpdateEvents = (Vec<u64>, Vec<(u64, Vec2)>, Vec<Vec2>, Vec<KillEvent>);
+
+fn default_rng() -> GameRng {
+    GameRng::seed_from_u64(0)
+}
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct GameState {
     pub players: Vec<Player>,
+    pub dead_players: Vec<DeadPlayer>,
     pub projectiles: Vec<ProjectileKind>,
     pub powerups: Vec<PowerUp>,
     pub lasers: Vec<LaserBeam>,
     pub killfeed: KillFeed,
     pub hurricane: Option<Hurricane>,
     pub debug_lines: Vec<DebugLine>,
+    pub round: Round,
+    pub scoreboard: Scoreboard,
+    // Spawn points of the map, random points are used if there are none
+    pub spawn_points: Vec<Vec2>,
+    pub teams: TeamConfig,
+    pub mode: GameModeKind,
+    // Number of updates since the start
+    pub tick: u64,
+    // Only the server simulates, so clients don't get these
+    #[serde(skip, default = "default_rng")]
+    pub rng: GameRng,
+    #[serde(skip)]
+    next_projectile_id: u64,
 }
 
 impl GameState {
     pub fn new() -> GameState {
+        Self::with_seed(0)
+    }
+
+    pub fn with_seed(seed: u64) -> GameState {
         GameState {
             players: Vec::new(),
+            dead_players: Vec::new(),
             projectiles: Vec::new(),
             powerups: Vec::new(),
             lasers: Vec::new(),
             killfeed: KillFeed::new(),
             hurricane: None,
             debug_lines: vec![],
+            round: Round::new(RoundConfig::default()),
+            scoreboard: Scoreboard::new(),
+            spawn_points: vec!(),
+            teams: TeamConfig::default(),
+            mode: FreeForAll.into(),
+            tick: 0,
+            rng: GameRng::seed_from_u64(seed),
+            next_projectile_id: 0,
+        }
+    }
+
+    /**
+     * Switches to another game mode, which should be done before anyone
+     * joins
+     */
+    pub fn set_mode(&mut self, mode: GameModeKind) {
+        if mode.requires_teams() {
+            self.teams.enabled = true;
         }
+        self.scoreboard.kills_count_for_team = mode.kills_count_for_team();
+        self.mode = mode;
+    }
+
+    /**
+     * Borrows the mode together with the parts of the state it works on
+     */
+    fn split_mode(&mut self) -> (&mut GameModeKind, ModeContext<'_>) {
+        let ctx = ModeContext {
+            players: &mut self.players,
+            dead_players: &self.dead_players,
+            scoreboard: &mut self.scoreboard,
+            killfeed: &mut self.killfeed,
+            teams: &self.teams,
+            rng: &mut self.rng,
+        };
+        (&mut self.mode, ctx)
+    }
+
+    /**
+     * Whether planes can shoot, races can be flown without weapons
+     */
+    pub fn weapons_enabled(&self) -> bool {
+        self.round.combat_enabled() && self.mode.weapons_enabled()
+    }
+
+    /**
+     * Whether dead players can respawn, they can't during a battle royale
+     * match
+     */
+    pub fn respawn_allowed(&self) -> bool {
+        self.mode.can_respawn()
     }
 
     /**
@@ -45,32 +137,153 @@ impl GameState {
      *  (
      *  vec with player ids that got hit with bullets,
      *  vec with positions where powerups where picked up,
-     *  vec with positions where lasers are fired
+     *  vec with positions where lasers are fired,
+     *  vec with the kills that happened
      *  )
      */
-    pub fn update(&mut self, delta: f32)
-        -> (Vec<u64>, Vec<(u64, Vec2)>, Vec<Vec2>)
-    {
+    pub fn update(&mut self, delta: f32) -> UpdateEvents {
+        self.tick += 1;
+        self.update_round(delta);
+        for dead_player in &mut self.dead_players {
+            dead_player.respawn_time -= delta;
+        }
         self.maybe_spawn_hurricane(delta);
         self.update_hurricane(delta);
         let hit_powerup_positions = self.handle_powerups();
-        let mut hit_players = self.handle_bullets(delta);
-        let fired_laser_positions = self.handle_lasers(delta);
-        hit_players.append(&mut self.handle_player_collisions(delta));
+        let mut hit_players = vec!();
+        let mut fired_laser_positions = vec!();
+        if self.round.combat_enabled() {
+            hit_players = self.handle_bullets(delta);
+            fired_laser_positions = self.handle_lasers(delta);
+            hit_players.append(&mut self.handle_player_collisions(delta));
+
+            let (mode, mut ctx) = self.split_mode();
+            hit_players.append(&mut mode.on_tick(delta, &mut ctx));
+            if mode.round_over(&ctx) {
+                self.round.finish();
+            }
+        }
         self.killfeed.manage_killfeed(delta);
-        (hit_players, hit_powerup_positions, fired_laser_positions)
+        self.scoreboard.update(delta);
+        let kills = self.scoreboard.take_kills();
+        (hit_players, hit_powerup_positions, fired_laser_positions, kills)
+    }
+
+    fn update_round(&mut self, delta: f32) {
+        match self.round.update(delta, self.mode.best_score(&self.scoreboard)) {
+            Some(RoundPhase::Ended) => {
+                let (mode, ctx) = self.split_mode();
+                let winners = mode.winners(&ctx);
+                let results = mode.results(&ctx);
+                self.round.winners = winners;
+                self.round.results = results;
+                if let Some(winner) = self.round.results.first() {
+                    let msg = format!("{} won round {}!", winner.name, self.round.number);
+                    self.killfeed.add_message(&msg);
+                }
+
+                self.powerups.clear();
+                self.hurricane = None;
+                self.projectiles.clear();
+                self.lasers.clear();
+                self.mode.on_round_end();
+            }
+            Some(RoundPhase::Warmup) => self.start_warmup(),
+            Some(RoundPhase::InProgress) => {
+                // Kills during the warmup don't count
+                self.scoreboard.reset();
+                let (mode, mut ctx) = self.split_mode();
+                mode.on_round_start(&mut ctx);
+                if self.mode.respawn_on_start() {
+                    self.respawn_everyone();
+                }
+                let msg = format!("Round {} has started!", self.round.number);
+                self.killfeed.add_message(&msg);
+            }
+            Some(RoundPhase::Intermission) | None => {}
+        }
+    }
+
+    fn start_warmup(&mut self) {
+        self.respawn_everyone();
+        let (mode, mut ctx) = self.split_mode();
+        mode.on_warmup(&mut ctx);
+        let msg = format!("Warmup for round {}", self.round.number);
+        self.killfeed.add_message(&msg);
+    }
+
+    /**
+     * Throws the current round away without a winner and starts the warmup
+     * of the next one
+     */
+    pub fn restart_round(&mut self) {
+        self.powerups.clear();
+        self.hurricane = None;
+        self.projectiles.clear();
+        self.lasers.clear();
+        self.mode.on_round_end();
+        self.scoreboard.reset();
+        self.round.restart();
+        self.start_warmup();
+    }
+
+    /**
+     * Switches to another game mode while people are playing, and restarts
+     * the round in it. Everyone is put on a team or taken off their team
+     * to match the new mode.
+     */
+    pub fn change_mode(&mut self, mode: GameModeKind, teams: TeamConfig) {
+        self.teams = teams;
+        self.set_mode(mode);
+        let mut ids = vec!();
+        for player in self.players.iter_mut()
+            .chain(self.dead_players.iter_mut().map(|dead| &mut dead.player))
+        {
+            player.team = None;
+            ids.push(player.id);
+        }
+        for id in ids {
+            let team = if self.teams.enabled {Some(self.smallest_team())} else {None};
+            let player = self.players.iter_mut()
+                .chain(self.dead_players.iter_mut().map(|dead| &mut dead.player))
+                .find(|p| p.id == id)
+                .unwrap();
+            player.team = team;
+            self.scoreboard.set_team(id, team);
+        }
+        self.restart_round();
+    }
+
+    /**
+     * Revives the dead players and puts everyone at a new spawn point
+     */
+    fn respawn_everyone(&mut self) {
+        let revived = self.dead_players.drain(..).map(|dead| dead.player);
+        self.players.extend(revived);
+        if self.teams.enabled {
+            self.balance_teams();
+        }
+        // Players that are already placed count as threats for the ones
+        // placed after them
+        for i in 0..self.players.len() {
+            let (position, rotation) = self.find_spawn_point(self.players[i].id);
+            let player = &mut self.players[i];
+            player.reset(position);
+            player.rotation = rotation;
+            self.mode.on_spawn(player);
+        }
     }
 
     fn maybe_spawn_hurricane(&mut self, delta: f32) {
         match self.hurricane {
-            None => {
-                let rand_number = rand::thread_rng().gen_range(0., 1.);
+            None if self.round.combat_enabled() => {
+                let rand_number = self.rng.gen_range(0., 1.);
                 if rand_number < constants::HURRICANE_PROBABILITY*delta {
-                    let xv = rand::thread_rng().gen_range(0., 1.)*constants::HURRICANE_MOVE_SPEED;
-                    let yv = rand::thread_rng().gen_range(0., 1.)*constants::HURRICANE_MOVE_SPEED;
+                    let xv = self.rng.gen_range(0., 1.)*constants::HURRICANE_MOVE_SPEED;
+                    let yv = self.rng.gen_range(0., 1.)*constants::HURRICANE_MOVE_SPEED;
 
-                    let xp = rand::thread_rng().gen_range(0., 1.)*constants::WORLD_SIZE;
-                    let yp = rand::thread_rng().gen_range(0., 1.)*constants::WORLD_SIZE;
+                    let xp = self.rng.gen_range(0., 1.)*constants::WORLD_SIZE;
+                    let yp = self.rng.gen_range(0., 1.)*constants::WORLD_SIZE;
 
                     let vel = vec2(xv, yv);
                     let pos = vec2(xp, yp);
@@ -93,12 +306,146 @@ impl GameState {
         }
     }
 
-    pub fn add_player(&mut self, player: Player) {
+    /**
+     * Puts a new plane at a safe spawn point and adds it to the game
+     */
+    pub fn join(&mut self, id: u64, name: String, plane: PlaneType, color: Color) {
+        let (position, rotation) = self.find_spawn_point(id);
+        let mut player = Player::new(id, position, plane, color, name);
+        player.rotation = rotation;
+        self.add_player(player);
+    }
+
+    pub fn add_player(&mut self, mut player: Player) {
+        // Players that rejoin from the menu replace their old plane
+        self.players.retain(|p| p.id != player.id);
+        self.dead_players.retain(|dead| dead.player.id != player.id);
+        player.team = if self.teams.enabled {Some(self.smallest_team())} else {None};
+        self.scoreboard.add_player(player.id, &player.name, player.team);
+        if !self.mode.on_join(&player) {
+            // Wait for the next round
+            let msg = format!("{} will join next round.", player.name);
+            self.killfeed.add_message(&msg);
+            self.dead_players.push(DeadPlayer::new(player));
+            return;
+        }
         self.players.push(player.clone());
-        let msg = player.name + " has joined the game.";
+        let msg = match player.team {
+            Some(team) => format!("{} has joined the {} team.", player.name, team.name()),
+            None => player.name + " has joined the game.",
+        };
         self.killfeed.add_message(&msg);
     }
 
+    fn team_size(&self, team: Team) -> usize {
+        self.players.iter()
+            .chain(self.dead_players.iter().map(|dead| &dead.player))
+            .filter(|p| p.team == Some(team))
+            .count()
+    }
+
+    /**
+     * The team with the fewest players, or the losing team if they are even
+     */
+    fn smallest_team(&self) -> Team {
+        *TEAMS.iter()
+            .min_by_key(|team| {
+                (self.team_size(**team), self.scoreboard.team_scores.get(team).cloned().unwrap_or(0))
+            })
+            .unwrap()
+    }
+
+    /**
+     * Moves players from the largest to the smallest team until the sizes
+     * differ by at most one. Only the alive players are moved, so this
+     * should be done when everyone is revived.
+     */
+    fn balance_teams(&mut self) {
+        loop {
+            let smallest = self.smallest_team();
+            let largest = *TEAMS.iter().max_by_key(|team| self.team_size(**team)).unwrap();
+            if self.team_size(largest) <= self.team_size(smallest) + 1 {
+                break;
+            }
+            // The most recently joined players are moved first
+            let player = self.players.iter_mut().rev()
+                .find(|p| p.team == Some(largest))
+                .unwrap();
+            player.team = Some(smallest);
+            self.scoreboard.set_team(player.id, player.team);
+            let msg = format!("{} was moved to the {} team.", player.name, smallest.name());
+            self.killfeed.add_message(&msg);
+        }
+    }
+
+    fn player_teams(&self) -> HashMap<u64, Option<Team>> {
+        self.players.iter()
+            .chain(self.dead_players.iter().map(|dead| &dead.player))
+            .map(|p| (p.id, p.team))
+            .collect()
+    }
+
+    /**
+     * Removes a player that left the game
+     */
+    pub fn remove_player(&mut self, id: u64) {
+        self.players.retain(|p| p.id != id);
+        self.dead_players.retain(|dead| dead.player.id != id);
+        self.scoreboard.remove_player(id);
+        self.mode.on_leave(id);
+    }
+
+    /**
+     * Moves players without health to the dead players
+     */
+    pub fn remove_dead_players(&mut self) {
+        let (dead, alive) = self.players.drain(..)
+            .partition::<Vec<_>, _>(|player| player.health <= 0);
+        self.players = alive;
+        self.dead_players.extend(dead.into_iter().map(DeadPlayer::new));
+    }
+
+    /**
+     * Brings back a dead player whose respawn time is up, optionally in a
+     * different plane. Returns false if the player can't respawn yet.
+     */
+    pub fn respawn_player(&mut self, id: u64, plane: PlaneType) -> bool {
+        if !self.mode.can_respawn() {
+            return false;
+        }
+        let index = match self.dead_players.iter()
+            .position(|dead| dead.player.id == id && dead.can_respawn())
+        {
+            Some(index) => index,
+            None => return false,
+        };
+        let mut player = self.dead_players.remove(index).player;
+        let (position, rotation) = self.find_spawn_point(id);
+        player.planetype = plane;
+        player.reset(position);
+        player.rotation = rotation;
+        self.mode.on_spawn(&mut player);
+        self.players.push(player);
+        true
+    }
+
+    /**
+     * Finds a spawn point away from enemies, projectiles and the hurricane.
+     * Returns the position and the rotation the plane should start with.
+     */
+    pub fn find_spawn_point(&mut self, id: u64) -> (Vec2, f32) {
+        // The search looks at the whole state, so it gets a copy of the
+        // generator which then takes its place
+        let mut rng = self.rng.clone();
+        let spawn_point = spawn::find_spawn_point(self, id, &mut rng);
+        self.rng = rng;
+        spawn_point
+    }
+
+    pub fn get_dead_player_by_id(&self, id: u64) -> Option<&DeadPlayer> {
+        self.dead_players.iter().find(|dead| dead.player.id == id)
+    }
+
     pub fn get_player_by_id(&self, id: u64) -> Option<&Player> {
         for player in &self.players {
             if player.id == id {
@@ -108,8 +455,59 @@ impl GameState {
         None
     }
 
-    pub fn add_bullet(&mut self, projectile: ProjectileKind) {
-        self.projectiles.push(ProjectileKind::from(projectile))
+    /**
+     * Flies the plane of a player with their input. Returns the bullet that
+     * was fired, which the server adds after sending the state to the player.
+     */
+    pub fn apply_input(
+        &mut self,
+        id: u64,
+        input: &ClientInput,
+        delta_time: f32,
+        sounds: &mut Vec<(SoundEffect, Vec2)>,
+    ) -> Option<ProjectileKind> {
+        let weapons_enabled = self.weapons_enabled();
+        let hurricane = &self.hurricane;
+        let player = self.players.iter_mut().find(|p| p.id == id)?;
+        player.update(input.x_input, input.y_input, hurricane, delta_time);
+
+        let mut bullet = None;
+        if input.shooting && weapons_enabled {
+            let (b, start_charging_laser) = player.shoot();
+            bullet = b;
+            if start_charging_laser {
+                sounds.push((SoundEffect::LaserCharge, player.position));
+            }
+        }
+
+        if input.activating_powerup {
+            player.trigger_powerup_if_available();
+        }
+
+        if player.health <= 0 {
+            sounds.push((SoundEffect::Explosion, player.position));
+        }
+        bullet
+    }
+
+    pub fn fire(
+        &mut self,
+        id: u64,
+        bullet: ProjectileKind,
+        sounds: &mut Vec<(SoundEffect, Vec2)>,
+    ) {
+        sounds.push((SoundEffect::Gun, bullet.get_position()));
+        self.scoreboard.record_shot(id);
+        self.add_bullet(bullet);
+    }
+
+    /**
+     * Adds a projectile, giving it the next free id
+     */
+    pub fn add_bullet(&mut self, mut projectile: ProjectileKind) {
+        projectile.set_id(self.next_projectile_id);
+        self.next_projectile_id += 1;
+        self.projectiles.push(projectile)
     }
 
     /**
@@ -119,11 +517,14 @@ impl GameState {
     pub fn handle_powerups(&mut self) -> Vec<(u64, Vec2)> {
         let mut new_powerups = self.powerups.clone();
         let mut hit_powerup_positions = vec!();
+        let mode = &mut self.mode;
         for player in &mut self.players {
             new_powerups = new_powerups.into_iter()
                 .filter_map(|powerup| {
                     let hit_radius = PLANE_SIZE + POWERUP_RADIUS;
-                    if (powerup.position - player.position).norm() < hit_radius as f32 {
+                    if (powerup.position - player.position).norm() < hit_radius as f32
+                        && mode.on_powerup_pickup(player, powerup.kind)
+                    {
                         // Add the powerup
                         player.add_powerup(powerup.kind);
                         hit_powerup_positions.push((player.id, player.position));
@@ -138,21 +539,22 @@ impl GameState {
         self.powerups = new_powerups;
 
         // Create new powerups if there are too few left
-        while self.powerups.len() < constants::POWERUP_AMOUNT as usize {
-            let x = random::<f32>() * constants::WORLD_SIZE as f32;
-            let y = random::<f32>() * constants::WORLD_SIZE as f32;
+        let powerup_amount = self.mode.powerup_amount();
+        while self.round.combat_enabled() && self.powerups.len() < powerup_amount {
+            let x = self.rng.gen::<f32>() * constants::WORLD_SIZE;
+            let y = self.rng.gen::<f32>() * constants::WORLD_SIZE;
             self.powerups.push(
-                PowerUp::new(Self::create_powerup(), vec2(x, y))
+                PowerUp::new(Self::create_powerup(&mut self.rng), vec2(x, y))
             )
         }
         hit_powerup_positions
     }
 
-    fn create_powerup() -> PowerUpKind {
+    fn create_powerup(rng: &mut GameRng) -> PowerUpKind {
         let dist = WeightedIndex::new(
             PowerUpKind::iter().map(|p| p.get_likelihood())
         ).unwrap();
-        PowerUpKind::iter().nth(dist.sample(&mut rand::thread_rng())).unwrap()
+        PowerUpKind::iter().nth(dist.sample(rng)).unwrap()
     }
 
     pub fn handle_bullets(&mut self, delta_time: f32) -> Vec<u64> {
@@ -167,23 +569,41 @@ impl GameState {
         let mut hit_players: Vec<u64> = Vec::new();
         let hit_radius = PLANE_SIZE * BULLET_RADIUS;
         let mut bullets_to_remove = vec!();
+        let player_teams = self.player_teams();
 
         for projectile in &mut self.projectiles {
-            let killer = projectile.get_shooter_name().clone();
-            
+            let killer = projectile.get_shooter_name();
+            let killer_id = projectile.get_shooter();
+            let weapon = projectile.get_weapon();
+            let shooter_team = player_teams.get(&killer_id).cloned().flatten();
+
             for player in &mut self.players {
+                if player.health <= 0 {
+                    continue;
+                }
+                let friendly = player.id != killer_id &&
+                    teams::teammates(shooter_team, player.team);
+                let damage = match self.teams.damage(friendly, projectile.get_damage()) {
+                    Some(damage) => damage,
+                    // Friendly fire is off, fly through teammates
+                    None => continue,
+                };
                 let distance = (projectile.get_position() - player.position).norm();
                 if distance < hit_radius as f32 && projectile.is_armed() {
-                    player.damage_player(projectile.get_damage());
+                    let damage = match self.mode.on_damage(killer_id, player, damage, weapon) {
+                        Some(damage) => damage,
+                        None => continue,
+                    };
+                    let health_before = player.health;
+                    player.damage_player(damage);
+                    self.scoreboard.record_damage(
+                        killer_id, player.id, health_before - player.health, weapon
+                    );
                     if player.has_died() {
-                        let msg = if projectile.get_id() == player.id {
-                            String::from(&player.name.clone()) + " killed themselves using a Gun."
-                        } else {
-                            killer.clone() + " killed " + 
-                                &player.name + " using a Gun."
-                        };
-
-                        self.killfeed.add_message(&msg.clone());
+                        self.scoreboard.record_kill(killer_id, player.id, weapon);
+                        let killer = if killer_id == player.id {None} else {Some(killer.as_str())};
+                        let msg = self.mode.on_kill(killer, player, weapon);
+                        self.killfeed.add_message(&msg);
                     }
                     bullets_to_remove.push(projectile.get_id());
                     hit_players.push(player.id);
@@ -203,18 +623,21 @@ impl GameState {
         let mut new_lasers = vec!();
         let mut fired_laser_positions = vec!();
         for player in &self.players {
-            player.maybe_get_laser().map(|l| {
+            if let Some(l) = player.maybe_get_laser() {
                 new_lasers.push(l);
                 fired_laser_positions.push(player.position);
-            });
+                self.scoreboard.record_shot(player.id);
+            }
         }
         self.lasers.append(&mut new_lasers);
         self.lasers.retain(|l| !l.should_be_removed());
+        let player_teams = self.player_teams();
 
         for laser in &mut self.lasers {
             laser.update(delta);
 
             let killer = laser.owner_name.clone();
+            let shooter_team = player_teams.get(&laser.owner).cloned().flatten();
 
             // Check collision
             let direction = Vec2::from_direction(
@@ -224,9 +647,14 @@ impl GameState {
 
             let hit_radius = PLANE_SIZE + constants::LASER_RANGE_EXTRA;
             for player in &mut self.players {
-                if player.id == laser.owner {
+                if player.id == laser.owner || player.health <= 0 {
                     continue
                 }
+                let friendly = teams::teammates(shooter_team, player.team);
+                let damage = match self.teams.damage(friendly, laser.damage) {
+                    Some(damage) => damage,
+                    None => continue,
+                };
                 let mut lowest_distance = 100000.;
                 for step in 0..100 {
                     let position = laser.position +
@@ -240,11 +668,19 @@ impl GameState {
                     // even though last phase is shown of the laser
                     if distance < hit_radius as f32 && laser.lifetime > 0. {
                         // bullets_to_remove.push(bullet.id);
-                        player.damage_player(laser.damage);
+                        let damage = match self.mode.on_damage(laser.owner, player, damage, Weapon::Laser) {
+                            Some(damage) => damage,
+                            None => break,
+                        };
+                        let health_before = player.health;
+                        player.damage_player(damage);
+                        self.scoreboard.record_damage(
+                            laser.owner, player.id, health_before - player.health, Weapon::Laser
+                        );
 
                         if player.has_died() {
-                            let msg = killer.clone() + " killed " +
-                                &player.name + " using a Laser.";
+                            self.scoreboard.record_kill(laser.owner, player.id, Weapon::Laser);
+                            let msg = self.mode.on_kill(Some(&killer), player, Weapon::Laser);
                             self.killfeed.add_message(&msg);
                         }
 
@@ -257,14 +693,21 @@ impl GameState {
     }
 
     pub fn handle_player_collisions(&mut self, delta: f32) -> Vec<u64> {
-        let mut collided_players: Vec<(u64, String)> = vec!();
+        let mut collided_players: Vec<(u64, u64, String, i16)> = vec!();
         let hit_radius = PLANE_SIZE * 2;
 
         for p1 in &self.players {
             for p2 in &self.players {
                 let distance = (p1.position - p2.position).norm();
                 if p1.id != p2.id && distance < hit_radius as f32 {
-		    collided_players.push((p1.id, p2.name.clone()));
+                    let friendly = teams::teammates(p1.team, p2.team);
+                    let damage = self.teams.damage(friendly, constants::COLLISION_DAMAGE)
+                        .and_then(|damage| {
+                            self.mode.on_damage(p2.id, p1, damage, Weapon::Collision)
+                        });
+                    if let Some(damage) = damage {
+                        collided_players.push((p1.id, p2.id, p2.name.clone(), damage));
+                    }
                 }
             }
         }
@@ -273,17 +716,22 @@ impl GameState {
         for player in &mut self.players {
             player.update_collision_timer(delta);
 
-            for (id, attacker) in &collided_players {
-                if player.id == *id && player.time_to_next_collision == 0. {
-                    let took_damage = player.damage_player(constants::COLLISION_DAMAGE);
+            for (id, attacker_id, attacker, damage) in &collided_players {
+                if player.id == *id && player.time_to_next_collision == 0. && player.health > 0 {
+                    let health_before = player.health;
+                    let took_damage = player.damage_player(*damage);
+                    self.scoreboard.record_damage(
+                        *attacker_id, player.id, health_before - player.health, Weapon::Collision
+                    );
 
                     if took_damage {
                         damaged_players.push(player.id);
                     }
                     
                     if player.has_died() {
-                        let msg = format!("{} killed {} by collision.", attacker.clone(), &player.name.clone());
-                        self.killfeed.add_message(msg.as_str());
+                        self.scoreboard.record_kill(*attacker_id, player.id, Weapon::Collision);
+                        let msg = self.mode.on_kill(Some(attacker), player, Weapon::Collision);
+                        self.killfeed.add_message(&msg);
                     }
 
                     player.time_to_next_collision = constants::COLLISION_GRACE_PERIOD;
@@ -0,0 +1,314 @@
+use std::collections::HashMap;
+use std::io::{self, prelude::*};
+
+use rand::{RngCore, SeedableRng};
+use serde::Serialize;
+use serde_derive::{Serialize, Deserialize};
+
+use crate::constants;
+use crate::gamemode::GameModeKind;
+use crate::gamestate::{GameRng, GameState};
+use crate::messages::{ClientInput, PROTOCOL_VERSION};
+use crate::player::{Color, PlaneType};
+use crate::projectiles::Projectile;
+use crate::replay::{invalid_data, read_record, write_record};
+use crate::teams::TeamConfig;
+
+pub const INPUT_LOG_EXTENSION: &str = "plenlog";
+
+/**
+ * The start of an input log. Everything random comes from the seed, so the
+ * state before the first tick and the seed are enough to play the log again.
+ */
+#[derive(Serialize, Deserialize, Clone)]
+pub struct InputLogHeader {
+    pub protocol_version: u32,
+    pub seed: u64,
+    // Unix time of the first tick
+    pub started: u64,
+    pub initial_state: Vec<u8>,
+}
+
+/**
+ * Everything the server does to the game state, in the order it does it
+ */
+#[derive(Serialize, Deserialize, Clone)]
+pub enum StateOp {
+    Update { delta: f32 },
+    Join { id: u64, name: String, plane: PlaneType, color: Color },
+    Leave { id: u64 },
+    Respawn { id: u64, plane: PlaneType },
+    // Applies the input to the plane and fires what it shot. No input
+    // means the same input as last time.
+    Fly { id: u64, input: Option<ClientInput> },
+    RemoveDeadPlayers,
+    // Voted for by the players
+    ChangeMode { mode: GameModeKind, teams: TeamConfig },
+    RestartRound,
+}
+
+/**
+ * Checksums of the parts of the game state after a tick. Players and
+ * projectiles are checked one by one to tell which one went wrong.
+ */
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct Checksums {
+    // Alive and dead players by id
+    pub players: Vec<(u64, u32)>,
+    pub projectiles: Vec<(u64, u32)>,
+    pub powerups: u32,
+    pub lasers: u32,
+    pub hurricane: u32,
+    // The round, scoreboard, mode and tick
+    pub game: u32,
+    pub rng: u32,
+}
+
+/**
+ * 32 bit FNV-1a of the encoded value
+ */
+fn checksum(value: &impl Serialize) -> u32 {
+    let bytes = bincode::serialize(value).expect("Failed to encode game state");
+    bytes.iter().fold(0x811c9dc5, |hash, byte| (hash ^ *byte as u32).wrapping_mul(0x01000193))
+}
+
+/**
+ * Finds the first id whose checksum differs between the two lists
+ */
+fn first_different_entity(kind: &str, expected: &[(u64, u32)], actual: &[(u64, u32)])
+    -> Option<String>
+{
+    for (id, hash) in expected {
+        match actual.iter().find(|(other, _)| other == id) {
+            None => return Some(format!("{} {} is missing", kind, id)),
+            Some((_, other)) if other != hash => return Some(format!("{} {} differs", kind, id)),
+            _ => {}
+        }
+    }
+    actual.iter()
+        .find(|(id, _)| !expected.iter().any(|(other, _)| other == id))
+        .map(|(id, _)| format!("{} {} should not exist", kind, id))
+}
+
+impl Checksums {
+    pub fn new(state: &GameState) -> Self {
+        // Killfeed messages and debug lines don't affect the game
+        Self {
+            players: state.players.iter()
+                .map(|player| (player.id, checksum(player)))
+                .chain(state.dead_players.iter().map(|dead| (dead.player.id, checksum(dead))))
+                .collect(),
+            projectiles: state.projectiles.iter()
+                .map(|projectile| (projectile.get_id(), checksum(projectile)))
+                .collect(),
+            powerups: checksum(&state.powerups),
+            lasers: checksum(&state.lasers),
+            hurricane: checksum(&state.hurricane),
+            game: checksum(&(&state.round, &state.scoreboard, &state.mode, state.tick)),
+            rng: state.rng.clone().next_u32(),
+        }
+    }
+
+    /**
+     * Describes the first part of the state that differs from the expected
+     * one, or None if all of it matches
+     */
+    pub fn first_difference(&self, actual: &Checksums) -> Option<String> {
+        let parts = [
+            (self.powerups, actual.powerups, "powerups"),
+            (self.lasers, actual.lasers, "lasers"),
+            (self.hurricane, actual.hurricane, "hurricane"),
+            (self.game, actual.game, "round, scoreboard or mode"),
+            (self.rng, actual.rng, "random number generator"),
+        ];
+        first_different_entity("player", &self.players, &actual.players)
+            .or_else(|| first_different_entity("projectile", &self.projectiles, &actual.projectiles))
+            .or_else(|| {
+                parts.iter()
+                    .find(|(expected, actual, _)| expected != actual)
+                    .map(|(_, _, name)| format!("{} differ", name))
+            })
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct TickRecord {
+    pub ops: Vec<StateOp>,
+    // The state after the ops
+    pub checksums: Checksums,
+}
+
+pub struct InputLogWriter<W: Write> {
+    out: W,
+    ops: Vec<StateOp>,
+    // The last input logged for each player, repeated inputs are left out
+    inputs: HashMap<u64, ClientInput>,
+    bytes_written: u64,
+}
+
+impl<W: Write> InputLogWriter<W> {
+    /**
+     * Starts a log of the state, which must not have been updated yet
+     */
+    pub fn new(mut out: W, seed: u64, started: u64, state: &GameState) -> io::Result<Self> {
+        let header = InputLogHeader {
+            protocol_version: PROTOCOL_VERSION,
+            seed,
+            started,
+            initial_state: bincode::serialize(state).expect("Failed to encode game state"),
+        };
+        let bytes_written = write_record(&mut out, &header)?;
+        Ok(Self {
+            out,
+            ops: vec!(),
+            inputs: HashMap::new(),
+            bytes_written,
+        })
+    }
+
+    pub fn push(&mut self, op: StateOp) {
+        self.ops.push(op);
+    }
+
+    pub fn fly(&mut self, id: u64, input: &ClientInput) {
+        let input = if self.inputs.get(&id) == Some(input) {
+            None
+        } else {
+            self.inputs.insert(id, *input);
+            Some(*input)
+        };
+        self.ops.push(StateOp::Fly { id, input });
+    }
+
+    /**
+     * Writes the ops of the tick along with the checksums of the state
+     * they led to
+     */
+    pub fn end_tick(&mut self, state: &GameState) -> io::Result<()> {
+        let record = TickRecord {
+            ops: std::mem::take(&mut self.ops),
+            checksums: Checksums::new(state),
+        };
+        self.bytes_written += write_record(&mut self.out, &record)?;
+        Ok(())
+    }
+
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+pub struct InputLogReader<R: Read> {
+    input: R,
+    pub header: InputLogHeader,
+}
+
+impl<R: Read> InputLogReader<R> {
+    pub fn new(mut input: R) -> io::Result<Self> {
+        let header: InputLogHeader = read_record(&mut input)?
+            .ok_or(invalid_data("Input log is empty"))?;
+        if header.protocol_version != PROTOCOL_VERSION {
+            return Err(invalid_data(&format!(
+                "Input log was recorded with protocol version {}, this is version {}",
+                header.protocol_version,
+                PROTOCOL_VERSION
+            )));
+        }
+        Ok(Self { input, header })
+    }
+
+    pub fn next_tick(&mut self) -> io::Result<Option<TickRecord>> {
+        match read_record(&mut self.input) {
+            // The server is usually stopped in the middle of writing a tick
+            Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+            result => result,
+        }
+    }
+}
+
+/**
+ * A game state being played again from an input log
+ */
+pub struct Simulation {
+    pub state: GameState,
+    inputs: HashMap<u64, ClientInput>,
+    // Planes fly with the delta time of the last update
+    delta_time: f32,
+}
+
+impl Simulation {
+    pub fn new(header: &InputLogHeader) -> io::Result<Self> {
+        let mut state: GameState = bincode::deserialize(&header.initial_state)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        state.rng = GameRng::seed_from_u64(header.seed);
+        Ok(Self {
+            state,
+            inputs: HashMap::new(),
+            delta_time: constants::DELTA_TIME,
+        })
+    }
+
+    pub fn apply(&mut self, op: &StateOp) {
+        // The sounds are only played by clients
+        let mut sounds = vec!();
+        match op {
+            StateOp::Update { delta } => {
+                self.delta_time = *delta;
+                self.state.update(*delta);
+            }
+            StateOp::Join { id, name, plane, color } => {
+                self.state.join(*id, name.clone(), *plane, color.clone());
+            }
+            StateOp::Leave { id } => self.state.remove_player(*id),
+            StateOp::Respawn { id, plane } => {
+                self.state.respawn_player(*id, *plane);
+            }
+            StateOp::Fly { id, input } => {
+                if let Some(input) = input {
+                    self.inputs.insert(*id, *input);
+                }
+                let input = self.inputs.get(id).cloned().unwrap_or(ClientInput::new());
+                let bullet = self.state.apply_input(*id, &input, self.delta_time, &mut sounds);
+                if let Some(bullet) = bullet {
+                    self.state.fire(*id, bullet, &mut sounds);
+                }
+            }
+            StateOp::RemoveDeadPlayers => self.state.remove_dead_players(),
+            StateOp::ChangeMode { mode, teams } => {
+                self.state.change_mode(mode.clone(), teams.clone());
+            }
+            StateOp::RestartRound => self.state.restart_round(),
+        }
+    }
+}
+
+pub enum Verification {
+    Matches { ticks: u64 },
+    // The first tick after which the state was not the same as when it was
+    // logged, and what differed
+    Diverged { tick: u64, difference: String },
+}
+
+/**
+ * Plays an input log again and compares the state after every tick with
+ * the logged checksums
+ */
+pub fn verify(input: impl Read) -> io::Result<Verification> {
+    let mut reader = InputLogReader::new(input)?;
+    let mut simulation = Simulation::new(&reader.header)?;
+    let mut ticks = 0;
+    while let Some(record) = reader.next_tick()? {
+        for op in &record.ops {
+            simulation.apply(op);
+        }
+        if let Some(difference) = record.checksums.first_difference(&Checksums::new(&simulation.state)) {
+            return Ok(Verification::Diverged { tick: simulation.state.tick, difference });
+        }
+        ticks += 1;
+    }
+    Ok(Verification::Matches { ticks })
+}
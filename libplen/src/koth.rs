@@ -0,0 +1,188 @@
+use serde_derive::{Serialize, Deserialize};
+
+use crate::constants;
+use crate::gamemode::{GameMode, ModeContext, ModeName};
+use crate::math::{self, Vec2, vec2};
+use crate::player::Player;
+use crate::scoreboard::Scoreboard;
+use crate::teams::Team;
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct ZoneConfig {
+    pub position: (f32, f32),
+    pub radius: f32,
+    // Zones with a velocity drift across the map like the hurricane
+    pub velocity: (f32, f32),
+}
+
+impl Default for ZoneConfig {
+    fn default() -> Self {
+        Self {
+            position: (constants::WORLD_SIZE * 0.5, constants::WORLD_SIZE * 0.5),
+            radius: 300.,
+            velocity: (0., 0.),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct KothConfig {
+    pub zones: Vec<ZoneConfig>,
+    pub points_per_second: f32,
+}
+
+impl Default for KothConfig {
+    fn default() -> Self {
+        Self {
+            zones: vec!(ZoneConfig::default()),
+            points_per_second: 1.,
+        }
+    }
+}
+
+/**
+ * Who holds a zone, teams hold zones together in team games
+ */
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum Holder {
+    Player(u64),
+    Team(Team),
+}
+
+impl Holder {
+    fn of(player: &Player) -> Self {
+        match player.team {
+            Some(team) => Holder::Team(team),
+            None => Holder::Player(player.id),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ControlZone {
+    pub position: Vec2,
+    pub velocity: Vec2,
+    pub radius: f32,
+    // The only holder with planes in the zone, or the last one if contested
+    pub holder: Option<Holder>,
+    // Planes of several holders are inside, nobody gets points
+    pub contested: bool,
+    start_position: Vec2,
+    // Points earned by the holder that haven't been handed out yet
+    pending_points: f32,
+}
+
+impl ControlZone {
+    fn new(config: &ZoneConfig) -> Self {
+        let position = vec2(config.position.0, config.position.1);
+        Self {
+            position,
+            velocity: vec2(config.velocity.0, config.velocity.1),
+            radius: config.radius,
+            holder: None,
+            contested: false,
+            start_position: position,
+            pending_points: 0.,
+        }
+    }
+
+    pub fn contains(&self, position: Vec2) -> bool {
+        math::wrapped_distance(self.position, position) < self.radius
+    }
+}
+
+/**
+ * King of the hill. Holding a zone alone earns points every second, planes
+ * of anyone else in the zone freeze it until only one holder is left.
+ */
+#[derive(Serialize, Deserialize, Clone)]
+pub struct KingOfTheHill {
+    pub zones: Vec<ControlZone>,
+    points_per_second: f32,
+}
+
+impl KingOfTheHill {
+    pub fn new(config: &KothConfig) -> Self {
+        Self {
+            zones: config.zones.iter().map(ControlZone::new).collect(),
+            points_per_second: config.points_per_second,
+        }
+    }
+
+    /**
+     * Moves the zones back to where they started and makes them neutral
+     */
+    pub fn reset(&mut self) {
+        for zone in &mut self.zones {
+            zone.position = zone.start_position;
+            zone.holder = None;
+            zone.contested = false;
+            zone.pending_points = 0.;
+        }
+    }
+
+    pub fn update(&mut self, delta: f32, players: &[Player], scoreboard: &mut Scoreboard) {
+        for zone in &mut self.zones {
+            zone.position = math::wrap_around(zone.position + zone.velocity * delta);
+
+            let inside: Vec<&Player> = players.iter()
+                .filter(|p| p.health > 0 && zone.contains(p.position))
+                .collect();
+            let first = inside.first().map(|p| Holder::of(p));
+            zone.contested = inside.iter().any(|p| Some(Holder::of(p)) != first);
+            if zone.contested {
+                // Frozen until only one holder is left
+                continue;
+            }
+
+            if first != zone.holder {
+                zone.holder = first;
+                zone.pending_points = 0.;
+            }
+            if let Some(holder) = zone.holder {
+                zone.pending_points += self.points_per_second * delta;
+                let points = zone.pending_points.floor();
+                zone.pending_points -= points;
+                if points > 0. {
+                    let ids: Vec<u64> = inside.iter().map(|p| p.id).collect();
+                    Self::award(holder, &ids, points as u32, scoreboard);
+                }
+            }
+        }
+    }
+
+    fn award(holder: Holder, players_inside: &[u64], points: u32, scoreboard: &mut Scoreboard) {
+        if let Holder::Team(team) = holder {
+            scoreboard.add_team_score(team, points);
+        }
+        for id in players_inside {
+            scoreboard.record_zone_points(*id, points);
+        }
+    }
+}
+
+impl GameMode for KingOfTheHill {
+    fn name(&self) -> ModeName {
+        ModeName::KingOfTheHill
+    }
+
+    // In team games only the zones give team score
+    fn kills_count_for_team(&self) -> bool {
+        false
+    }
+
+    fn on_tick(&mut self, delta: f32, ctx: &mut ModeContext) -> Vec<u64> {
+        self.update(delta, ctx.players, ctx.scoreboard);
+        vec!()
+    }
+
+    fn on_warmup(&mut self, _ctx: &mut ModeContext) {
+        self.reset();
+    }
+
+    fn on_round_start(&mut self, _ctx: &mut ModeContext) {
+        self.reset();
+    }
+}
@@ -8,3 +8,17 @@ pub mod killfeed;
 pub mod messages;
 pub mod hurricane;
 pub mod debug;
+pub mod round;
+pub mod scoreboard;
+pub mod stats;
+pub mod spawn;
+pub mod teams;
+pub mod ctf;
+pub mod koth;
+pub mod battle_royale;
+pub mod race;
+pub mod gamemode;
+pub mod bot;
+pub mod replay;
+pub mod inputlog;
+pub mod log;
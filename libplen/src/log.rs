@@ -0,0 +1,248 @@
+use std::fmt::{self, Write as FmtWrite};
+use std::io::Write;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde_derive::{Serialize, Deserialize};
+
+// Log lines go to stderr, with the time, level and module they came from.
+// The level and format are picked by `init`, the PLEN_LOG and
+// PLEN_LOG_FORMAT environment variables override it. Fields come before
+// the message:
+//
+//     info!(client = id, address = address; "Got new connection");
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, PartialOrd, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    pub fn name(&self) -> &str {
+        match self {
+            Level::Error => "error",
+            Level::Warn => "warn",
+            Level::Info => "info",
+            Level::Debug => "debug",
+            Level::Trace => "trace",
+        }
+    }
+}
+
+impl FromStr for Level {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "error" => Ok(Level::Error),
+            "warn" => Ok(Level::Warn),
+            "info" => Ok(Level::Info),
+            "debug" => Ok(Level::Debug),
+            "trace" => Ok(Level::Trace),
+            _ => Err(format!("Unknown log level {}", s)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    // One line of text per message, fields as key=value
+    Human,
+    // One json object per line
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "human" => Ok(Format::Human),
+            "json" => Ok(Format::Json),
+            _ => Err(format!("Unknown log format {}", s)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct LogConfig {
+    pub level: Level,
+    pub format: Format,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            level: Level::Info,
+            format: Format::Human,
+        }
+    }
+}
+
+static LEVEL: AtomicUsize = AtomicUsize::new(Level::Info as usize);
+static JSON: AtomicBool = AtomicBool::new(false);
+
+/**
+ * Sets the level and format, unless they are set in the environment
+ */
+pub fn init(config: &LogConfig) {
+    let from_env = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+    let level = match from_env("PLEN_LOG").map(|value| value.parse()) {
+        Some(Ok(level)) => level,
+        Some(Err(e)) => {
+            eprintln!("{}, using {}", e, config.level.name());
+            config.level
+        }
+        None => config.level,
+    };
+    let format = match from_env("PLEN_LOG_FORMAT").map(|value| value.parse()) {
+        Some(Ok(format)) => format,
+        Some(Err(e)) => {
+            eprintln!("{}", e);
+            config.format
+        }
+        None => config.format,
+    };
+    LEVEL.store(level as usize, Ordering::Relaxed);
+    JSON.store(format == Format::Json, Ordering::Relaxed);
+}
+
+pub fn enabled(level: Level) -> bool {
+    level as usize <= LEVEL.load(Ordering::Relaxed)
+}
+
+/**
+ * Formats a unix time in milliseconds as an RFC 3339 time in UTC
+ */
+fn format_time(millis: u64) -> String {
+    let seconds = millis / 1000;
+    let days = (seconds / 86400) as i64;
+    let time = seconds % 86400;
+
+    // Days since 1970 to a date in the proleptic Gregorian calendar
+    let z = days + 719468;
+    let era = z / 146097;
+    let day_of_era = z - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year, month, day, time / 3600, time / 60 % 60, time % 60, millis % 1000
+    )
+}
+
+fn push_json_string(out: &mut String, text: &str) {
+    out.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => { let _ = write!(out, "\\u{:04x}", c as u32); }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/**
+ * Writes a log line, use the macros instead of calling this
+ */
+pub fn write(level: Level, target: &str, fields: &[(&str, &dyn fmt::Display)], message: fmt::Arguments) {
+    let millis = SystemTime::now().duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0);
+    let time = format_time(millis);
+
+    let mut line = String::new();
+    if JSON.load(Ordering::Relaxed) {
+        line.push_str("{\"time\":");
+        push_json_string(&mut line, &time);
+        line.push_str(",\"level\":");
+        push_json_string(&mut line, level.name());
+        line.push_str(",\"target\":");
+        push_json_string(&mut line, target);
+        line.push_str(",\"message\":");
+        push_json_string(&mut line, &message.to_string());
+        for (key, value) in fields {
+            line.push(',');
+            push_json_string(&mut line, key);
+            line.push(':');
+            push_json_string(&mut line, &value.to_string());
+        }
+        line.push('}');
+    } else {
+        let _ = write!(line, "{} {:5} {}: {}", time, level.name().to_uppercase(), target, message);
+        for (key, value) in fields {
+            let value = value.to_string();
+            if value.is_empty() || value.contains(char::is_whitespace) || value.contains('"') {
+                let _ = write!(line, " {}={:?}", key, value);
+            } else {
+                let _ = write!(line, " {}={}", key, value);
+            }
+        }
+    }
+    line.push('\n');
+
+    // One write per line so lines from different threads don't mix
+    let _ = std::io::stderr().write_all(line.as_bytes());
+}
+
+#[macro_export]
+macro_rules! log {
+    ($level:expr, $($key:ident = $value:expr),+ ; $($arg:tt)+) => {
+        if $crate::log::enabled($level) {
+            $crate::log::write(
+                $level,
+                module_path!(),
+                &[$((stringify!($key), &$value as &dyn std::fmt::Display)),+],
+                format_args!($($arg)+)
+            );
+        }
+    };
+    ($level:expr, $($arg:tt)+) => {
+        if $crate::log::enabled($level) {
+            $crate::log::write($level, module_path!(), &[], format_args!($($arg)+));
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! error {
+    ($($arg:tt)+) => { $crate::log!($crate::log::Level::Error, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! warn {
+    ($($arg:tt)+) => { $crate::log!($crate::log::Level::Warn, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)+) => { $crate::log!($crate::log::Level::Info, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)+) => { $crate::log!($crate::log::Level::Debug, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! trace {
+    ($($arg:tt)+) => { $crate::log!($crate::log::Level::Trace, $($arg)+) };
+}
@@ -119,3 +119,16 @@ pub fn angle_diff(source_angle: f32, target_angle: f32) -> f32 {
     use std::f32::consts::PI;
     modulo(target_angle - source_angle + PI, 2. * PI) - PI
 }
+
+/**
+ * Returns the shortest vector from one point to another in the wrapping world
+ */
+pub fn wrapped_difference(from: Vec2, to: Vec2) -> Vec2 {
+    let half = constants::WORLD_SIZE / 2.;
+    let wrap = |d: f32| modulo(d + half, constants::WORLD_SIZE) - half;
+    vec2(wrap(to.x - from.x), wrap(to.y - from.y))
+}
+
+pub fn wrapped_distance(a: Vec2, b: Vec2) -> f32 {
+    wrapped_difference(a, b).norm()
+}
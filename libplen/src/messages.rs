@@ -25,8 +25,12 @@ impl MessageReader {
         }
     }
 
-    pub fn fetch_bytes(&mut self) -> io::Result<()> {
+    /**
+     * Reads what has arrived so far, returning the number of bytes read
+     */
+    pub fn fetch_bytes(&mut self) -> io::Result<usize> {
         let mut buffer = [1; 64];
+        let mut total = 0;
         loop {
             let amount = match self.stream.read(&mut buffer) {
                 Ok(amount) => amount,
@@ -34,8 +38,9 @@ impl MessageReader {
                 e => e?,
             };
             if amount == 0 {
-                break Ok(());
+                break Ok(total);
             }
+            total += amount;
             self.byte_queue.extend(buffer.iter().take(amount));
         }
     }
@@ -74,19 +79,61 @@ impl Iterator for MessageIterator<'_> {
     }
 }
 
+/**
+ * Changed whenever the messages or the game state are encoded differently,
+ * replays of other versions can't be played
+ */
+pub const PROTOCOL_VERSION: u32 = 1;
+
 #[derive(Serialize, Deserialize, Clone, Copy)]
 pub enum SoundEffect { Powerup, Explosion, Gun, LaserCharge, LaserFire }
 
 #[derive(Serialize, Deserialize)]
 pub enum ServerMessage {
     AssignId(u64),
-    GameState(crate::gamestate::GameState),
+    GameState(Box<crate::gamestate::GameState>),
     PlaySound(SoundEffect, Vec2),
     PlayerHit(u64),
     YouDied,
+    // Sent right before the server closes the connection, with the reason
+    Kicked(String),
+    // The players with the most kills, and the stats of the receiving player
+    LifetimeStats {
+        top: Vec<(String, crate::stats::LifetimeStats)>,
+        mine: Option<crate::stats::LifetimeStats>
+    },
+    // Answer to a ping, with the number it was sent with
+    Pong(u64),
+    // A chat message from a player, or a notice from the server if there is
+    // no sender. The time is when the server got it, in unix seconds.
+    Chat { sender: Option<u64>, name: String, channel: ChatChannel, time: u64, text: String },
+    // The running vote, sent whenever it changes. None when it is over.
+    Vote(Option<VoteStatus>),
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct VoteStatus {
+    // What is being voted on, like "Kick Griefer"
+    pub description: String,
+    pub initiator: String,
+    pub yes: usize,
+    pub no: usize,
+    // Yes votes needed for the vote to pass
+    pub needed: usize,
+    pub time_left: f32,
+    // How the receiving client voted
+    pub voted: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub enum ChatChannel {
+    All,
+    Team,
+    // Seen by the sender and the receiver, who is named for the sender
+    Whisper { to: String },
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
 pub struct ClientInput {
     pub x_input: f32,
     pub y_input: f32,
@@ -109,4 +156,14 @@ impl ClientInput {
 pub enum ClientMessage {
     Input(ClientInput),
     JoinGame { name: String, plane: player::PlaneType, color: player::Color },
+    // Watch the game without a plane, leaving it if already playing
+    Spectate,
+    RequestLifetimeStats,
+    // Leave the dead players once the respawn time is up
+    Respawn { plane: player::PlaneType },
+    // Asks the server to answer with a pong right away, to measure latency
+    Ping(u64),
+    Chat(String),
+    // Yes or no to the running vote
+    Vote(bool),
 }
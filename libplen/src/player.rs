@@ -6,8 +6,9 @@ use crate::projectiles::{self, LaserBeam, ProjectileKind};
 use crate::math::{self, Vec2, vec2};
 use crate::hurricane::Hurricane;
 use crate::powerups::{PowerUpKind, AppliedPowerup};
+use crate::teams::Team;
 
-#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Enum)]
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Enum)]
 pub enum PlaneType {
     SukaBlyat,
     HowdyCowboy,
@@ -131,12 +132,17 @@ pub struct Player {
     pub available_powerup: Option<PowerUpKind>,
     pub planetype: PlaneType,
     pub color: Color,
+    pub team: Option<Team>,
+    // Flag carriers can't turn invisible
+    pub has_flag: bool,
     pub name: String,
     pub has_used_gun: bool,
     // Time until laser burst
     pub laser_charge_time: Option<f32>,
     pub lasering_this_frame: bool,
     pub time_to_next_collision: f32,
+    // Time left of spawn protection, the plane can't take damage or shoot
+    pub spawn_protection: f32,
 }
 
 
@@ -162,17 +168,41 @@ impl Player {
             cooldown: 0.,
             planetype: plane_type,
             color: color,
+            team: None,
+            has_flag: false,
             name: name,
             has_used_gun: false,
             laser_charge_time: None,
             lasering_this_frame: false,
             time_to_next_collision: constants::COLLISION_GRACE_PERIOD,
+            spawn_protection: constants::SPAWN_PROTECTION_TIME,
         }
     }
 
+    /**
+     * Puts the plane back in the starting condition at a new position
+     */
+    pub fn reset(&mut self, position: Vec2) {
+        self.position = position;
+        self.health = self.max_health();
+        self.speed = 0.;
+        self.velocity = vec2(0., 0.);
+        self.angular_velocity = 0.;
+        self.wind_effect_velocity = vec2(0., 0.);
+        self.cooldown = 0.;
+        self.powerups = vec!(AppliedPowerup::new(PowerUpKind::Gun));
+        self.available_powerup = None;
+        self.laser_charge_time = None;
+        self.lasering_this_frame = false;
+        self.time_to_next_collision = constants::COLLISION_GRACE_PERIOD;
+        self.spawn_protection = constants::SPAWN_PROTECTION_TIME;
+        self.has_flag = false;
+    }
+
     pub fn update(
         &mut self, x_input: f32, y_input: f32, hurricane: &Option<Hurricane>, delta_time: f32
     ) {
+        self.spawn_protection = (self.spawn_protection - delta_time).max(0.);
         self.update_laser_charge(delta_time);
         self.update_velocity_and_position(y_input, hurricane, delta_time);
         self.update_angular_velocity_and_rotation(x_input, delta_time);
@@ -246,8 +276,12 @@ impl Player {
         self.powerups.iter().any(|powerup|powerup.kind == kind)
     }
 
+    pub fn is_spawn_protected(&self) -> bool {
+        self.spawn_protection > 0.
+    }
+
     pub fn damage_player(&mut self, damage: i16) -> bool {
-        if self.invincibility_is_on() {
+        if self.invincibility_is_on() || self.is_spawn_protected() {
             return false;
         }
 
@@ -277,7 +311,7 @@ impl Player {
      * whether a laser started charging)
      */
     pub fn shoot(&mut self) -> (Option<ProjectileKind>, bool) {
-        if !self.invincibility_is_on() {
+        if !self.invincibility_is_on() && !self.is_spawn_protected() {
             if self.weapon_is_wielded(PowerUpKind::Laser) {
                 // Start charging the laser
                 if let None = self.laser_charge_time {
@@ -301,6 +335,7 @@ impl Player {
                     dir,
                     self.planetype.firepower(),
                     self.id,
+                    self.team,
                     self.velocity.norm(),
                     self.name.clone(),
                 );
@@ -420,6 +455,39 @@ impl Player {
     }
 
     pub fn is_invisible(&self) -> bool {
-        self.powerups.iter().any(|p| p.kind == PowerUpKind::Invisible)
+        !self.has_flag && self.powerups.iter().any(|p| p.kind == PowerUpKind::Invisible)
+    }
+
+    /**
+     * The team color in team games, otherwise the color the player picked
+     */
+    pub fn display_color(&self) -> Color {
+        match self.team {
+            Some(team) => team.color(),
+            None => self.color.clone(),
+        }
+    }
+}
+
+/**
+ * A player waiting to respawn, kept in the game so that the player keeps
+ * their plane, team and name
+ */
+#[derive(Serialize, Deserialize, Clone)]
+pub struct DeadPlayer {
+    pub player: Player,
+    pub respawn_time: f32,
+}
+
+impl DeadPlayer {
+    pub fn new(player: Player) -> Self {
+        Self {
+            player,
+            respawn_time: constants::RESPAWN_DELAY,
+        }
+    }
+
+    pub fn can_respawn(&self) -> bool {
+        self.respawn_time <= 0.
     }
 }
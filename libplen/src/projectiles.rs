@@ -1,5 +1,3 @@
-use rand::Rng;
-
 use enum_dispatch::enum_dispatch;
 use serde_derive::{Serialize, Deserialize};
 
@@ -7,6 +5,8 @@ use crate::constants;
 use crate::math::{self, Vec2};
 use crate::hurricane::Hurricane;
 use crate::player::Player;
+use crate::teams::{self, Team};
+use crate::scoreboard::Weapon;
 
 
 #[enum_dispatch]
@@ -24,10 +24,12 @@ pub trait Projectile {
 
     // Accessor functions
     fn get_id(&self) -> u64;
+    fn set_id(&mut self, id: u64);
     fn get_shooter(&self) -> u64;
     fn get_shooter_name(&self) -> String;
     fn get_position(&self) -> Vec2;
     fn get_damage(&self) -> i16;
+    fn get_weapon(&self) -> Weapon;
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -46,9 +48,9 @@ impl Bullet {
     pub fn new(position: Vec2, velocity: Vec2, damage: i16, owner: u64, owner_name: String)
         -> Bullet
     {
-        let mut rng = rand::thread_rng();
         Bullet {
-            id: rng.gen_range(0, u64::max_value()),
+            // Given by the game state when the bullet is added
+            id: 0,
             position,
             velocity,
             traveled_distance: 0.,
@@ -85,6 +87,8 @@ impl Projectile for Bullet {
     fn get_position(&self) -> Vec2 {self.position}
     fn get_damage(&self) -> i16 {self.damage}
     fn get_id(&self) -> u64 {self.id}
+    fn set_id(&mut self, id: u64) {self.id = id}
+    fn get_weapon(&self) -> Weapon {Weapon::Gun}
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -141,6 +145,7 @@ pub struct Missile {
     pub lifetime: f32,
     pub damage: i16,
     pub owner: u64,
+    pub team: Option<Team>,
     pub owner_name: String,
     pub speed: f32,
 }
@@ -151,18 +156,20 @@ impl Missile {
         angle: f32,
         damage: i16,
         owner: u64,
+        team: Option<Team>,
         speed: f32,
         owner_name: String,
     ) -> Self {
-        let mut rng = rand::thread_rng();
         Self {
-            id: rng.gen_range(0, u64::max_value()),
+            // Given by the game state when the missile is added
+            id: 0,
             position,
             angular_velocity: 0.,
             angle,
             damage,
             lifetime: 0.,
             owner,
+            team,
             owner_name,
             speed,
         }
@@ -176,8 +183,8 @@ impl Projectile for Missile {
         self.lifetime += delta_time;
         // Check if there are players in the line of sight of the missile
         let to_track = players.iter()
-            // Don't track the shooter
-            .filter(|p| p.id != self.owner)
+            // Don't track the shooter or their teammates
+            .filter(|p| p.id != self.owner && !teams::teammates(self.team, p.team))
             // Calculate the angle to the missile
             .map(|p| {
                 let direction_to = p.position - self.position;
@@ -217,4 +224,6 @@ impl Projectile for Missile {
     fn get_position(&self) -> Vec2 {self.position}
     fn get_damage(&self) -> i16 {self.damage}
     fn get_id(&self) -> u64 {self.id}
+    fn set_id(&mut self, id: u64) {self.id = id}
+    fn get_weapon(&self) -> Weapon {Weapon::Missile}
 }
@@ -0,0 +1,361 @@
+use std::collections::BTreeMap;
+
+use serde_derive::{Serialize, Deserialize};
+
+use crate::constants;
+use crate::gamemode::{GameMode, ModeContext, ModeName};
+use crate::killfeed::KillFeed;
+use crate::math::{self, Vec2, vec2};
+use crate::player::{PlaneType, Player};
+use crate::round::RoundResult;
+use crate::scoreboard::Scoreboard;
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct RaceConfig {
+    // Checkpoints in the order they are flown through, the first one is the
+    // start and finish line
+    pub checkpoints: Vec<(f32, f32)>,
+    pub checkpoint_radius: f32,
+    // Laps needed to win a round, 0 means racing until the time runs out
+    pub laps: u32,
+    pub weapons: bool,
+}
+
+impl Default for RaceConfig {
+    fn default() -> Self {
+        // A ring around the middle of the map
+        let center = constants::WORLD_SIZE * 0.5;
+        let checkpoints = (0..8)
+            .map(|i| {
+                let angle = i as f32 / 8. * std::f32::consts::PI * 2.;
+                (center + angle.cos() * 1000., center + angle.sin() * 1000.)
+            })
+            .collect();
+        Self {
+            checkpoints,
+            checkpoint_radius: 150.,
+            laps: 3,
+            weapons: false,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub struct GhostFrame {
+    pub time: f32,
+    pub position: Vec2,
+    pub rotation: f32,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct LapRecord {
+    pub name: String,
+    pub time: f32,
+}
+
+#[derive(Clone, Copy)]
+pub struct LapEvent {
+    pub player: u64,
+    pub plane: PlaneType,
+    pub time: f32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Racer {
+    pub laps: u32,
+    pub next_checkpoint: usize,
+    // Race time when the current lap started, None before crossing the start
+    pub lap_start: Option<f32>,
+    pub best_lap: Option<f32>,
+    pub last_lap: Option<f32>,
+    // Time to each checkpoint in the current and the best lap
+    pub splits: Vec<f32>,
+    pub best_splits: Vec<f32>,
+    // Difference to the best lap at the last checkpoint, negative is faster
+    pub split_delta: Option<f32>,
+    // Where the ghost of the best lap for this plane is right now
+    pub ghost: Option<(Vec2, f32)>,
+    // Race time when the last checkpoint was passed, used for the standings
+    last_checkpoint_time: f32,
+    // The checkpoint the plane is still in after passing it, it has to
+    // leave before passing it again when a track has only one
+    #[serde(skip)]
+    inside: Option<usize>,
+    #[serde(skip)]
+    path: Vec<GhostFrame>,
+}
+
+impl Racer {
+    /**
+     * Throws away the current lap, the plane has to cross the start again
+     */
+    fn abort_lap(&mut self) {
+        self.next_checkpoint = 0;
+        self.lap_start = None;
+        self.splits.clear();
+        self.split_delta = None;
+        self.ghost = None;
+        self.path.clear();
+    }
+}
+
+/**
+ * Air race through a ring of checkpoints, with lap and split timing and a
+ * ghost of the best lap for every plane type
+ */
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Race {
+    pub checkpoints: Vec<Vec2>,
+    pub checkpoint_radius: f32,
+    pub laps: u32,
+    pub weapons: bool,
+    pub racers: BTreeMap<u64, Racer>,
+    // The best lap flown in every plane
+    pub records: BTreeMap<PlaneType, LapRecord>,
+    time: f32,
+    // Only the server keeps the recorded laps, clients get the ghost
+    // positions through the racers
+    #[serde(skip)]
+    ghosts: BTreeMap<PlaneType, Vec<GhostFrame>>,
+    #[serde(skip)]
+    lap_events: Vec<LapEvent>,
+}
+
+impl Race {
+    pub fn new(config: &RaceConfig) -> Self {
+        Self {
+            // The world wraps around, so checkpoints outside of it are moved
+            // to where a plane can reach them
+            checkpoints: config.checkpoints.iter()
+                .map(|(x, y)| math::wrap_around(vec2(*x, *y)))
+                .collect(),
+            checkpoint_radius: config.checkpoint_radius,
+            laps: config.laps,
+            weapons: config.weapons,
+            racers: BTreeMap::new(),
+            records: BTreeMap::new(),
+            time: 0.,
+            ghosts: BTreeMap::new(),
+            lap_events: vec!(),
+        }
+    }
+
+    /**
+     * Starts a new race, the records and ghosts are kept
+     */
+    pub fn reset(&mut self) {
+        self.racers.clear();
+    }
+
+    pub fn remove_player(&mut self, id: u64) {
+        self.racers.remove(&id);
+    }
+
+    pub fn update(&mut self, delta: f32, players: &[Player], killfeed: &mut KillFeed) {
+        self.time += delta;
+        if self.checkpoints.is_empty() {
+            return;
+        }
+
+        for player in players {
+            let racer = self.racers.entry(player.id).or_default();
+            if player.health <= 0 {
+                racer.abort_lap();
+                continue;
+            }
+
+            if let Some(lap_start) = racer.lap_start {
+                let lap_time = self.time - lap_start;
+                if racer.path.last().map(|f| lap_time - f.time >= constants::GHOST_FRAME_TIME)
+                    .unwrap_or(true)
+                {
+                    racer.path.push(GhostFrame {
+                        time: lap_time,
+                        position: player.position,
+                        rotation: player.rotation,
+                    });
+                }
+                racer.ghost = self.ghosts.get(&player.planetype)
+                    .and_then(|ghost| ghost_position(ghost, lap_time));
+            }
+
+            let radius = self.checkpoint_radius;
+            let checkpoints = &self.checkpoints;
+            let is_inside = |index: usize| {
+                math::wrapped_distance(checkpoints[index], player.position) <= radius
+            };
+            if racer.inside.map(|index| !is_inside(index)).unwrap_or(false) {
+                racer.inside = None;
+            }
+            if racer.inside == Some(racer.next_checkpoint) || !is_inside(racer.next_checkpoint) {
+                continue;
+            }
+            racer.inside = Some(racer.next_checkpoint);
+            racer.last_checkpoint_time = self.time;
+            let passed = racer.next_checkpoint;
+            racer.next_checkpoint = (racer.next_checkpoint + 1) % self.checkpoints.len();
+
+            match racer.lap_start {
+                // Crossing the start line for the first time
+                None => {
+                    racer.lap_start = Some(self.time);
+                    racer.path.clear();
+                }
+                Some(lap_start) if passed == 0 => {
+                    let lap_time = self.time - lap_start;
+                    racer.laps += 1;
+                    racer.last_lap = Some(lap_time);
+                    if racer.best_lap.map(|best| lap_time < best).unwrap_or(true) {
+                        racer.best_lap = Some(lap_time);
+                        racer.best_splits = racer.splits.clone();
+                    }
+                    racer.split_delta = None;
+                    racer.splits.clear();
+                    racer.lap_start = Some(self.time);
+
+                    let path = std::mem::take(&mut racer.path);
+                    let is_record = self.records.get(&player.planetype)
+                        .map(|record| lap_time < record.time)
+                        .unwrap_or(true);
+                    // Records of earlier sessions have no ghost, so the best
+                    // lap of this session is used until they are beaten
+                    let faster_than_ghost = self.ghosts.get(&player.planetype)
+                        .and_then(|ghost| ghost.last())
+                        .map(|frame| lap_time < frame.time)
+                        .unwrap_or(true);
+                    if faster_than_ghost {
+                        self.ghosts.insert(player.planetype, path);
+                    }
+                    if is_record {
+                        self.records.insert(
+                            player.planetype,
+                            LapRecord { name: player.name.clone(), time: lap_time }
+                        );
+                        let msg = format!(
+                            "{} set a new {} record: {:.2}s",
+                            player.name,
+                            player.planetype.name(),
+                            lap_time
+                        );
+                        killfeed.add_message(&msg);
+                    }
+                    self.lap_events.push(LapEvent {
+                        player: player.id,
+                        plane: player.planetype,
+                        time: lap_time
+                    });
+                }
+                Some(lap_start) => {
+                    let split = self.time - lap_start;
+                    racer.split_delta = racer.best_splits.get(racer.splits.len())
+                        .map(|best| split - best);
+                    racer.splits.push(split);
+                }
+            }
+        }
+    }
+
+    /**
+     * Loads the best laps of earlier sessions, they have no ghosts
+     */
+    pub fn set_records(&mut self, records: BTreeMap<PlaneType, LapRecord>) {
+        self.records = records;
+    }
+
+    pub fn take_laps(&mut self) -> Vec<LapEvent> {
+        std::mem::take(&mut self.lap_events)
+    }
+
+    pub fn winner(&self) -> Option<u64> {
+        match self.laps {
+            0 => None,
+            laps => self.racers.iter()
+                .find(|(_, racer)| racer.laps >= laps)
+                .map(|(id, _)| *id),
+        }
+    }
+
+    /**
+     * Player ids ordered by how far they have come, the first one to pass
+     * a checkpoint is ahead
+     */
+    pub fn standings(&self) -> Vec<u64> {
+        let checkpoints = self.checkpoints.len().max(1);
+        let progress = |racer: &Racer| {
+            // The start line is passed at the start of every lap
+            let passed = (racer.next_checkpoint + checkpoints - 1) % checkpoints;
+            let started = racer.lap_start.is_some() as usize;
+            racer.laps as usize * checkpoints + passed * started
+        };
+        let mut standings: Vec<_> = self.racers.iter().collect();
+        standings.sort_by(|(_, a), (_, b)| {
+            progress(b).cmp(&progress(a))
+                .then(a.last_checkpoint_time.partial_cmp(&b.last_checkpoint_time).unwrap())
+        });
+        standings.into_iter().map(|(id, _)| *id).collect()
+    }
+}
+
+impl GameMode for Race {
+    fn name(&self) -> ModeName {
+        ModeName::Race
+    }
+
+    fn on_leave(&mut self, id: u64) {
+        self.remove_player(id);
+    }
+
+    fn weapons_enabled(&self) -> bool {
+        self.weapons
+    }
+
+    fn on_tick(&mut self, delta: f32, ctx: &mut ModeContext) -> Vec<u64> {
+        self.update(delta, ctx.players, ctx.killfeed);
+        vec!()
+    }
+
+    fn on_round_start(&mut self, _ctx: &mut ModeContext) {
+        self.reset();
+    }
+
+    // Races are won by laps, kills don't end them
+    fn best_score(&self, _scoreboard: &Scoreboard) -> u32 {
+        0
+    }
+
+    fn round_over(&self, _ctx: &ModeContext) -> bool {
+        self.winner().is_some()
+    }
+
+    /**
+     * The standings, the score is the number of laps
+     */
+    fn results(&self, ctx: &ModeContext) -> Vec<RoundResult> {
+        self.standings().iter()
+            .filter_map(|id| {
+                let name = ctx.scoreboard.stats.get(id)?.name.clone();
+                Some(RoundResult { name, score: self.racers[id].laps })
+            })
+            .collect()
+    }
+
+    fn winners(&self, _ctx: &ModeContext) -> Vec<u64> {
+        self.standings().into_iter().take(1).collect()
+    }
+}
+
+/**
+ * Position and rotation of a ghost at a time into its lap, None when the
+ * ghost has finished
+ */
+fn ghost_position(ghost: &[GhostFrame], time: f32) -> Option<(Vec2, f32)> {
+    let next = ghost.iter().position(|frame| frame.time >= time)?;
+    if next == 0 {
+        return ghost.first().map(|frame| (frame.position, frame.rotation));
+    }
+    let (a, b) = (ghost[next - 1], ghost[next]);
+    let progress = (time - a.time) / (b.time - a.time).max(0.001);
+    let position = a.position + math::wrapped_difference(a.position, b.position) * progress;
+    Some((math::wrap_around(position), a.rotation + math::angle_diff(a.rotation, b.rotation) * progress))
+}
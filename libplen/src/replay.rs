@@ -0,0 +1,240 @@
+use std::io::{self, prelude::*};
+
+use serde::{de::DeserializeOwned, Serialize};
+use serde_derive::{Serialize, Deserialize};
+
+use crate::gamemode::ModeName;
+use crate::gamestate::GameState;
+use crate::math::Vec2;
+use crate::messages::SoundEffect;
+use crate::scoreboard::KillEvent;
+
+pub const REPLAY_EXTENSION: &str = "plenreplay";
+// A full snapshot is stored this often, the frames in between only store
+// what changed
+pub const KEYFRAME_INTERVAL: u32 = 100;
+// Far above the size of any game state, longer records are corrupt
+const MAX_RECORD_BYTES: usize = 64 * 1024 * 1024;
+// Unchanged bytes between two changes that are stored anyway, because a new
+// change costs more than that
+const DELTA_MERGE_DISTANCE: usize = 12;
+
+/**
+ * The start of a replay file
+ */
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ReplayHeader {
+    pub protocol_version: u32,
+    pub mode: ModeName,
+    // The server config file the match was played with
+    pub config: String,
+    // Unix time of the first frame
+    pub started: u64,
+    pub round: u32,
+}
+
+/**
+ * Things that happened during a tick that the game state doesn't show
+ */
+#[derive(Serialize, Deserialize, Clone)]
+pub enum ReplayEvent {
+    Sound(SoundEffect, Vec2),
+    PlayerHit(u64),
+    Death(KillEvent),
+}
+
+/**
+ * An encoded game state
+ */
+#[derive(Serialize, Deserialize, Clone)]
+pub enum Snapshot {
+    Full(Vec<u8>),
+    // The bytes that changed since the previous snapshot, by offset
+    Delta(Vec<(u32, Vec<u8>)>),
+}
+
+impl Snapshot {
+    fn encode(previous: &[u8], current: Vec<u8>, keyframe: bool) -> Self {
+        if keyframe || previous.len() != current.len() {
+            return Snapshot::Full(current);
+        }
+
+        let mut changes: Vec<(u32, Vec<u8>)> = vec!();
+        let mut last_change = None;
+        for (i, (old, new)) in previous.iter().zip(current.iter()).enumerate() {
+            if old == new {
+                continue;
+            }
+            match (changes.last_mut(), last_change) {
+                (Some((offset, bytes)), Some(last)) if i - last <= DELTA_MERGE_DISTANCE => {
+                    let start = *offset as usize + bytes.len();
+                    bytes.extend_from_slice(&current[start..=i]);
+                }
+                _ => changes.push((i as u32, vec!(*new))),
+            }
+            last_change = Some(i);
+        }
+        Snapshot::Delta(changes)
+    }
+
+    /**
+     * Returns the bytes of the game state, given the bytes of the previous
+     * one
+     */
+    pub fn decode(&self, previous: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            Snapshot::Full(bytes) => Ok(bytes.clone()),
+            Snapshot::Delta(changes) => {
+                let mut bytes = previous.to_vec();
+                for (offset, changed) in changes {
+                    let start = *offset as usize;
+                    let target = bytes.get_mut(start..start + changed.len())
+                        .ok_or(invalid_data("Replay delta is outside the previous snapshot"))?;
+                    target.copy_from_slice(changed);
+                }
+                Ok(bytes)
+            }
+        }
+    }
+
+    pub fn is_full(&self) -> bool {
+        match self {
+            Snapshot::Full(_) => true,
+            Snapshot::Delta(_) => false,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ReplayFrame {
+    pub tick: u32,
+    // Seconds since the first frame
+    pub time: f32,
+    pub snapshot: Snapshot,
+    pub events: Vec<ReplayEvent>,
+}
+
+pub(crate) fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/**
+ * Records are stored as a u32 length followed by the bincode encoding
+ */
+pub(crate) fn write_record(out: &mut impl Write, record: &impl Serialize) -> io::Result<u64> {
+    let data = bincode::serialize(record).expect("Failed to encode replay");
+    out.write_all(&(data.len() as u32).to_be_bytes())?;
+    out.write_all(&data)?;
+    Ok(4 + data.len() as u64)
+}
+
+/**
+ * Reads the next record, or None at the end of the file
+ */
+pub(crate) fn read_record<T: DeserializeOwned>(input: &mut impl Read) -> io::Result<Option<T>> {
+    let mut length = [0; 4];
+    match input.read_exact(&mut length) {
+        Ok(()) => {}
+        Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let length = u32::from_be_bytes(length) as usize;
+    if length > MAX_RECORD_BYTES {
+        return Err(invalid_data(&format!("Record of {} bytes is too large", length)));
+    }
+    let mut data = vec![0; length];
+    input.read_exact(&mut data)?;
+    bincode::deserialize(&data)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+pub struct ReplayWriter<W: Write> {
+    out: W,
+    previous: Vec<u8>,
+    tick: u32,
+    bytes_written: u64,
+}
+
+impl<W: Write> ReplayWriter<W> {
+    pub fn new(mut out: W, header: &ReplayHeader) -> io::Result<Self> {
+        let bytes_written = write_record(&mut out, header)?;
+        Ok(Self {
+            out,
+            previous: vec!(),
+            tick: 0,
+            bytes_written,
+        })
+    }
+
+    pub fn write_frame(
+        &mut self,
+        time: f32,
+        state: &GameState,
+        events: Vec<ReplayEvent>
+    ) -> io::Result<()> {
+        let current = bincode::serialize(state).expect("Failed to encode game state");
+        let keyframe = self.tick.is_multiple_of(KEYFRAME_INTERVAL);
+        let snapshot = Snapshot::encode(&self.previous, current.clone(), keyframe);
+        self.previous = current;
+
+        let frame = ReplayFrame { tick: self.tick, time, snapshot, events };
+        self.bytes_written += write_record(&mut self.out, &frame)?;
+        self.tick += 1;
+        Ok(())
+    }
+
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+pub struct ReplayReader<R: Read> {
+    input: R,
+    pub header: ReplayHeader,
+    previous: Vec<u8>,
+}
+
+impl<R: Read> ReplayReader<R> {
+    /**
+     * Reads the header, failing if the replay was recorded by another
+     * version of the game
+     */
+    pub fn new(mut input: R) -> io::Result<Self> {
+        let header: ReplayHeader = read_record(&mut input)?
+            .ok_or(invalid_data("Replay is empty"))?;
+        if header.protocol_version != crate::messages::PROTOCOL_VERSION {
+            return Err(invalid_data(&format!(
+                "Replay was recorded with protocol version {}, this is version {}",
+                header.protocol_version,
+                crate::messages::PROTOCOL_VERSION
+            )));
+        }
+        Ok(Self { input, header, previous: vec!() })
+    }
+
+    /**
+     * Reads the next frame without decoding its snapshot
+     */
+    pub fn next_raw_frame(&mut self) -> io::Result<Option<ReplayFrame>> {
+        read_record(&mut self.input)
+    }
+
+    /**
+     * Reads the next frame along with its game state
+     */
+    pub fn next_frame(&mut self) -> io::Result<Option<(ReplayFrame, GameState)>> {
+        let frame = match self.next_raw_frame()? {
+            Some(frame) => frame,
+            None => return Ok(None),
+        };
+        self.previous = frame.snapshot.decode(&self.previous)?;
+        let state = bincode::deserialize(&self.previous)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok(Some((frame, state)))
+    }
+}
@@ -0,0 +1,130 @@
+use serde_derive::{Serialize, Deserialize};
+
+use crate::constants;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum RoundPhase {
+    Warmup,
+    InProgress,
+    Ended,
+    Intermission,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct RoundConfig {
+    pub warmup_time: f32,
+    // Length of a round in seconds, 0 means no time limit
+    pub time_limit: f32,
+    // Score needed to win a round, 0 means no score limit
+    pub score_limit: u32,
+    pub intermission_time: f32,
+}
+
+impl Default for RoundConfig {
+    fn default() -> Self {
+        Self {
+            warmup_time: 10.,
+            time_limit: 600.,
+            score_limit: 20,
+            intermission_time: 15.,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RoundResult {
+    pub name: String,
+    pub score: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Round {
+    pub phase: RoundPhase,
+    // Time left of the current phase, infinite if the phase has no end time
+    pub time_left: f32,
+    pub number: u32,
+    // Final standings of the last round, best first
+    pub results: Vec<RoundResult>,
+    // Ids of the players that won the last round
+    pub winners: Vec<u64>,
+    config: RoundConfig,
+}
+
+impl Round {
+    pub fn new(config: RoundConfig) -> Self {
+        Self {
+            phase: RoundPhase::Warmup,
+            time_left: config.warmup_time,
+            number: 1,
+            results: vec!(),
+            winners: vec!(),
+            config,
+        }
+    }
+
+    /**
+     * Advances the round timer. Returns the new phase if the round moved on
+     * to another phase.
+     */
+    pub fn update(&mut self, delta: f32, best_score: u32) -> Option<RoundPhase> {
+        self.time_left -= delta;
+
+        let score_limit_reached = self.config.score_limit != 0 &&
+            best_score >= self.config.score_limit;
+
+        let next = match self.phase {
+            RoundPhase::Warmup if self.time_left <= 0. => Some(RoundPhase::InProgress),
+            RoundPhase::InProgress if self.time_left <= 0. || score_limit_reached => {
+                Some(RoundPhase::Ended)
+            }
+            RoundPhase::Ended if self.time_left <= 0. => Some(RoundPhase::Intermission),
+            RoundPhase::Intermission if self.time_left <= 0. => Some(RoundPhase::Warmup),
+            _ => None,
+        };
+
+        if let Some(phase) = next {
+            self.enter_phase(phase);
+        }
+        next
+    }
+
+    fn enter_phase(&mut self, phase: RoundPhase) {
+        self.phase = phase;
+        self.time_left = match phase {
+            RoundPhase::Warmup => self.config.warmup_time,
+            RoundPhase::InProgress if self.config.time_limit > 0. => self.config.time_limit,
+            RoundPhase::InProgress => f32::INFINITY,
+            RoundPhase::Ended => constants::ROUND_END_DURATION,
+            RoundPhase::Intermission => self.config.intermission_time,
+        };
+
+        if phase == RoundPhase::Warmup {
+            self.number += 1;
+        }
+    }
+
+    /**
+     * Skips to the warmup of the next round
+     */
+    pub fn restart(&mut self) {
+        self.enter_phase(RoundPhase::Warmup);
+    }
+
+    /**
+     * Ends the round at the next update, for modes with their own end
+     * conditions
+     */
+    pub fn finish(&mut self) {
+        if self.phase == RoundPhase::InProgress {
+            self.time_left = 0.;
+        }
+    }
+
+    pub fn combat_enabled(&self) -> bool {
+        match self.phase {
+            RoundPhase::Warmup | RoundPhase::InProgress => true,
+            RoundPhase::Ended | RoundPhase::Intermission => false,
+        }
+    }
+}
@@ -0,0 +1,297 @@
+use std::collections::BTreeMap;
+
+use serde_derive::{Serialize, Deserialize};
+
+use crate::constants;
+use crate::teams::{self, Team};
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Weapon {
+    Gun,
+    Missile,
+    Laser,
+    Collision,
+    Zone,
+}
+
+impl Weapon {
+    pub fn name(&self) -> &str {
+        match self {
+            Weapon::Gun => "Gun",
+            Weapon::Missile => "Missile",
+            Weapon::Laser => "Laser",
+            Weapon::Collision => "Collision",
+            Weapon::Zone => "Zone",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct PlayerStats {
+    pub name: String,
+    pub team: Option<Team>,
+    pub kills: u32,
+    pub deaths: u32,
+    pub assists: u32,
+    pub captures: u32,
+    // Points for holding control zones
+    pub zone_points: u32,
+    pub damage_dealt: u32,
+    pub damage_taken: u32,
+    pub shots_fired: u32,
+    pub shots_hit: u32,
+}
+
+impl PlayerStats {
+    pub fn score(&self) -> u32 {
+        self.kills * constants::KILL_SCORE
+            + self.assists * constants::ASSIST_SCORE
+            + self.captures * constants::CAPTURE_SCORE
+            + self.zone_points
+    }
+
+    pub fn accuracy(&self) -> f32 {
+        if self.shots_fired == 0 {
+            0.
+        } else {
+            (self.shots_hit as f32 / self.shots_fired as f32).min(1.)
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub struct KillEvent {
+    pub killer: u64,
+    pub victim: u64,
+    pub weapon: Weapon,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct DamageRecord {
+    attacker: u64,
+    victim: u64,
+    time: f32,
+}
+
+/**
+ * Per player statistics for the current round. Entries are kept when a
+ * player dies so they survive respawning, and are only removed when the
+ * player leaves the server.
+ */
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Scoreboard {
+    // Ordered maps keep the encoded state the same between runs
+    pub stats: BTreeMap<u64, PlayerStats>,
+    // Score of each team in team games
+    pub team_scores: BTreeMap<Team, u32>,
+    // Whether kills add to the team score, in objective modes only the
+    // objective does
+    pub kills_count_for_team: bool,
+    // Recent damage, used to hand out assists. Like the rest below only
+    // the server needs it.
+    #[serde(skip)]
+    recent_damage: Vec<DamageRecord>,
+    #[serde(skip)]
+    time: f32,
+    // Kills since the last call to take_kills
+    #[serde(skip)]
+    kills: Vec<KillEvent>,
+}
+
+impl Scoreboard {
+    pub fn new() -> Self {
+        Self {
+            stats: BTreeMap::new(),
+            team_scores: BTreeMap::new(),
+            kills_count_for_team: true,
+            recent_damage: vec!(),
+            time: 0.,
+            kills: vec!(),
+        }
+    }
+
+    pub fn update(&mut self, delta: f32) {
+        self.time += delta;
+        let time = self.time;
+        self.recent_damage.retain(|d| time - d.time < constants::ASSIST_TIME);
+    }
+
+    /**
+     * Adds a player or updates the existing entry of a player who rejoined
+     */
+    pub fn add_player(&mut self, id: u64, name: &str, team: Option<Team>) {
+        let stats = self.stats.entry(id).or_default();
+        stats.name = name.to_string();
+        stats.team = team;
+        if let Some(team) = team {
+            self.team_scores.entry(team).or_insert(0);
+        }
+    }
+
+    pub fn set_team(&mut self, id: u64, team: Option<Team>) {
+        if let Some(stats) = self.stats.get_mut(&id) {
+            stats.team = team;
+        }
+    }
+
+    fn team_of(&self, id: u64) -> Option<Team> {
+        self.stats.get(&id).and_then(|s| s.team)
+    }
+
+    fn are_teammates(&self, a: u64, b: u64) -> bool {
+        teams::teammates(self.team_of(a), self.team_of(b))
+    }
+
+    pub fn remove_player(&mut self, id: u64) {
+        self.stats.remove(&id);
+        self.recent_damage.retain(|d| d.attacker != id && d.victim != id);
+    }
+
+    /**
+     * Clears all statistics but keeps the players
+     */
+    pub fn reset(&mut self) {
+        for stats in self.stats.values_mut() {
+            *stats = PlayerStats {
+                name: stats.name.clone(),
+                team: stats.team,
+                .. PlayerStats::default()
+            };
+        }
+        for score in self.team_scores.values_mut() {
+            *score = 0;
+        }
+        self.recent_damage.clear();
+    }
+
+    pub fn record_shot(&mut self, shooter: u64) {
+        if let Some(stats) = self.stats.get_mut(&shooter) {
+            stats.shots_fired += 1;
+        }
+    }
+
+    pub fn record_damage(&mut self, attacker: u64, victim: u64, amount: i16, weapon: Weapon) {
+        let amount = amount.max(0) as u32;
+        if attacker != victim {
+            let friendly = self.are_teammates(attacker, victim);
+            if let Some(stats) = self.stats.get_mut(&attacker) {
+                stats.damage_dealt += amount;
+                if weapon != Weapon::Collision {
+                    stats.shots_hit += 1;
+                }
+            }
+            // Hurting a teammate doesn't help with the kill
+            if !friendly {
+                self.recent_damage.push(DamageRecord { attacker, victim, time: self.time });
+            }
+        }
+        if let Some(stats) = self.stats.get_mut(&victim) {
+            stats.damage_taken += amount;
+        }
+    }
+
+    /**
+     * Records a death. Everyone except the killer who damaged the victim
+     * recently gets an assist. Suicides and team kills count as a death but
+     * not as a kill.
+     */
+    pub fn record_kill(&mut self, killer: u64, victim: u64, weapon: Weapon) {
+        self.kills.push(KillEvent { killer, victim, weapon });
+        if killer != victim && !self.are_teammates(killer, victim) {
+            match self.team_of(killer) {
+                Some(team) if self.kills_count_for_team => {
+                    self.add_team_score(team, constants::KILL_SCORE);
+                }
+                _ => {}
+            }
+            if let Some(stats) = self.stats.get_mut(&killer) {
+                stats.kills += 1;
+            }
+        }
+        if let Some(stats) = self.stats.get_mut(&victim) {
+            stats.deaths += 1;
+        }
+
+        let mut assisters: Vec<u64> = self.recent_damage.iter()
+            .filter(|d| d.victim == victim && d.attacker != killer)
+            .map(|d| d.attacker)
+            .collect();
+        assisters.sort();
+        assisters.dedup();
+        for assister in assisters {
+            if let Some(stats) = self.stats.get_mut(&assister) {
+                stats.assists += 1;
+            }
+        }
+        self.recent_damage.retain(|d| d.victim != victim);
+    }
+
+    pub fn record_capture(&mut self, id: u64) {
+        if let Some(team) = self.team_of(id) {
+            self.add_team_score(team, constants::CAPTURE_SCORE);
+        }
+        if let Some(stats) = self.stats.get_mut(&id) {
+            stats.captures += 1;
+        }
+    }
+
+    pub fn record_zone_points(&mut self, id: u64, points: u32) {
+        if let Some(stats) = self.stats.get_mut(&id) {
+            stats.zone_points += points;
+        }
+    }
+
+    pub fn add_team_score(&mut self, team: Team, points: u32) {
+        *self.team_scores.entry(team).or_insert(0) += points;
+    }
+
+    pub fn take_kills(&mut self) -> Vec<KillEvent> {
+        std::mem::take(&mut self.kills)
+    }
+
+    pub fn score(&self, id: u64) -> u32 {
+        self.stats.get(&id).map(|s| s.score()).unwrap_or(0)
+    }
+
+    /**
+     * The best score of a player or a team, used for the score limit
+     */
+    pub fn best_score(&self) -> u32 {
+        self.stats.values().map(|s| s.score())
+            .chain(self.team_scores.values().cloned())
+            .max()
+            .unwrap_or(0)
+    }
+
+    /**
+     * Teams with the best score first, empty outside of team games
+     */
+    pub fn sorted_teams(&self) -> Vec<(Team, u32)> {
+        let mut teams: Vec<_> = self.team_scores.iter().map(|(t, s)| (*t, *s)).collect();
+        teams.sort_by(|(team_a, a), (team_b, b)| b.cmp(a).then(team_a.cmp(team_b)));
+        teams
+    }
+
+    pub fn winning_team(&self) -> Option<Team> {
+        self.sorted_teams().first().map(|(team, _)| *team)
+    }
+
+    /**
+     * All entries with the best score first
+     */
+    pub fn sorted(&self) -> Vec<(u64, &PlayerStats)> {
+        let mut entries: Vec<_> = self.stats.iter().map(|(id, s)| (*id, s)).collect();
+        entries.sort_by(|(id_a, a), (id_b, b)| {
+            b.score().cmp(&a.score())
+                .then(a.deaths.cmp(&b.deaths))
+                .then(id_a.cmp(id_b))
+        });
+        entries
+    }
+}
+
+impl Default for Scoreboard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
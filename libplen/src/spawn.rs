@@ -0,0 +1,111 @@
+use std::f32::consts::PI;
+
+use rand::Rng;
+
+use crate::constants;
+use crate::gamestate::GameState;
+use crate::math::{self, Vec2, vec2};
+use crate::projectiles::Projectile;
+use crate::teams;
+
+/**
+ * A position with a weight, the further away a spawn point is from threats
+ * the safer it is. Weights above 1 make a threat count as closer than it is.
+ */
+struct Threat {
+    position: Vec2,
+    weight: f32,
+}
+
+fn threats(state: &GameState, player_id: u64) -> Vec<Threat> {
+    let mut threats = vec!();
+    let team = state.get_player_by_id(player_id)
+        .or(state.get_dead_player_by_id(player_id).map(|dead| &dead.player))
+        .and_then(|p| p.team);
+    for player in &state.players {
+        // Teammates are safe to spawn next to
+        if player.id != player_id && !teams::teammates(team, player.team) {
+            threats.push(Threat { position: player.position, weight: 1. });
+        }
+    }
+    for projectile in &state.projectiles {
+        if projectile.get_shooter() != player_id {
+            threats.push(Threat {
+                position: projectile.get_position(),
+                weight: constants::SPAWN_PROJECTILE_WEIGHT,
+            });
+        }
+    }
+    for laser in &state.lasers {
+        threats.push(Threat { position: laser.position, weight: constants::SPAWN_LASER_WEIGHT });
+    }
+    threats
+}
+
+/**
+ * How safe a position is, higher is better
+ */
+fn safety(state: &GameState, threats: &[Threat], position: Vec2) -> f32 {
+    let mut safety = threats.iter()
+        .map(|threat| math::wrapped_distance(threat.position, position) / threat.weight)
+        .fold(constants::WORLD_SIZE, f32::min);
+
+    // Everything within the winds of the hurricane is unsafe, the closer to
+    // the eye wall the worse
+    if let Some(hurricane) = &state.hurricane {
+        let distance = math::wrapped_distance(hurricane.position, position);
+        let radius = hurricane.size() / 2.;
+        if distance < radius {
+            safety = safety.min(radius - distance).min(distance) * constants::SPAWN_HURRICANE_FACTOR;
+        }
+    }
+    safety
+}
+
+/**
+ * Direction in which the threats push, weighted by how close they are
+ */
+fn escape_direction(threats: &[Threat], position: Vec2) -> Vec2 {
+    threats.iter().fold(vec2(0., 0.), |sum, threat| {
+        let away = math::wrapped_difference(threat.position, position);
+        let distance = away.norm().max(1.);
+        sum + away / distance * (threat.weight / distance)
+    })
+}
+
+/**
+ * Picks the safest of a number of candidate points, either the spawn points
+ * of the map or random points in the world. Returns the position and a
+ * rotation that points the plane away from the threats.
+ */
+pub fn find_spawn_point(state: &GameState, player_id: u64, rng: &mut impl Rng) -> (Vec2, f32) {
+    let threats = threats(state, player_id);
+
+    let candidates: Vec<Vec2> = if state.spawn_points.is_empty() {
+        (0..constants::SPAWN_CANDIDATES)
+            .map(|_| vec2(
+                rng.gen_range(0., constants::WORLD_SIZE),
+                rng.gen_range(0., constants::WORLD_SIZE)
+            ))
+            .collect()
+    } else {
+        state.spawn_points.clone()
+    };
+
+    let position = candidates.into_iter()
+        // A bit of randomness so that equally safe spawn points are all used
+        .map(|candidate| (candidate, safety(state, &threats, candidate) * rng.gen_range(0.9, 1.)))
+        .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
+        .map(|(candidate, _)| candidate)
+        .unwrap_or(vec2(0., 0.));
+
+    let escape = escape_direction(&threats, position);
+    let direction = if escape.norm() > 0. {
+        escape.angle()
+    } else {
+        rng.gen_range(0., 2. * PI)
+    };
+
+    // A rotation of 0 flies upwards, see Player::set_final_velocity
+    (position, direction + PI / 2.)
+}
@@ -0,0 +1,110 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde_derive::{Serialize, Deserialize};
+
+use crate::player::PlaneType;
+use crate::scoreboard::{PlayerStats, Weapon};
+
+const SECONDS_PER_WEEK: u64 = 60 * 60 * 24 * 7;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Default)]
+pub struct WeekStats {
+    pub kills: u32,
+    pub deaths: u32,
+}
+
+/**
+ * Statistics of a player summed over all sessions
+ */
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct LifetimeStats {
+    pub kills: u32,
+    pub deaths: u32,
+    pub assists: u32,
+    pub damage_dealt: u32,
+    pub damage_taken: u32,
+    pub shots_fired: u32,
+    pub shots_hit: u32,
+    pub rounds_played: u32,
+    pub rounds_won: u32,
+    pub kills_per_plane: HashMap<PlaneType, u32>,
+    pub kills_per_weapon: HashMap<Weapon, u32>,
+    // Fastest race lap in seconds in every plane
+    pub best_laps: HashMap<PlaneType, f32>,
+    // Kills and deaths per week since the unix epoch
+    pub weeks: BTreeMap<u64, WeekStats>,
+    pub last_seen: u64,
+}
+
+impl LifetimeStats {
+    pub fn record_kill(&mut self, plane: PlaneType, weapon: Weapon, time: u64) {
+        self.kills += 1;
+        *self.kills_per_plane.entry(plane).or_insert(0) += 1;
+        *self.kills_per_weapon.entry(weapon).or_insert(0) += 1;
+        self.weeks.entry(time / SECONDS_PER_WEEK).or_default().kills += 1;
+        self.last_seen = time;
+    }
+
+    pub fn record_death(&mut self, time: u64) {
+        self.deaths += 1;
+        self.weeks.entry(time / SECONDS_PER_WEEK).or_default().deaths += 1;
+        self.last_seen = time;
+    }
+
+    /**
+     * Adds the stats of a finished round that are not recorded per kill
+     */
+    pub fn record_round(&mut self, round: &PlayerStats, won: bool, time: u64) {
+        self.assists += round.assists;
+        self.damage_dealt += round.damage_dealt;
+        self.damage_taken += round.damage_taken;
+        self.shots_fired += round.shots_fired;
+        self.shots_hit += round.shots_hit;
+        self.rounds_played += 1;
+        if won {
+            self.rounds_won += 1;
+        }
+        self.last_seen = time;
+    }
+
+    /**
+     * Records a race lap, returns true if it is the best lap in this plane
+     */
+    pub fn record_lap(&mut self, plane: PlaneType, time: f32) -> bool {
+        let best = self.best_laps.entry(plane).or_insert(f32::INFINITY);
+        if time < *best {
+            *best = time;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn kill_death_ratio(&self) -> f32 {
+        self.kills as f32 / self.deaths.max(1) as f32
+    }
+
+    pub fn favourite_plane(&self) -> Option<PlaneType> {
+        self.kills_per_plane.iter()
+            .max_by_key(|(_, kills)| **kills)
+            .map(|(plane, _)| *plane)
+    }
+
+    pub fn favourite_weapon(&self) -> Option<Weapon> {
+        self.kills_per_weapon.iter()
+            .max_by_key(|(_, kills)| **kills)
+            .map(|(weapon, _)| *weapon)
+    }
+
+    /**
+     * Kill death ratio of the last `count` weeks, oldest first
+     */
+    pub fn recent_weeks(&self, now: u64, count: u64) -> Vec<(u64, WeekStats)> {
+        let this_week = now / SECONDS_PER_WEEK;
+        let first = this_week.saturating_sub(count.max(1) - 1);
+        self.weeks.range(first..=this_week)
+            .map(|(week, stats)| (*week, *stats))
+            .collect()
+    }
+}
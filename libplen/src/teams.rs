@@ -0,0 +1,103 @@
+use serde_derive::{Serialize, Deserialize};
+
+use crate::constants;
+use crate::player::Color;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum Team {
+    Red,
+    Blue,
+}
+
+pub const TEAMS: [Team; 2] = [Team::Red, Team::Blue];
+
+impl Team {
+    pub fn name(&self) -> &str {
+        match self {
+            Team::Red => "Red",
+            Team::Blue => "Blue",
+        }
+    }
+
+    pub fn color(&self) -> Color {
+        match self {
+            Team::Red => Color::Red,
+            Team::Blue => Color::Blue,
+        }
+    }
+
+    /**
+     * Color modulation for the plane textures, lighter than the team color
+     * so the planes stay recognizable
+     */
+    pub fn tint(&self) -> (u8, u8, u8) {
+        match self {
+            Team::Red => (255, 140, 140),
+            Team::Blue => (140, 160, 255),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum FriendlyFire {
+    Off,
+    Reduced,
+    Full,
+}
+
+impl FriendlyFire {
+    /**
+     * Damage dealt to a teammate, None if teammates can't be hit at all
+     */
+    pub fn scale_damage(&self, damage: i16) -> Option<i16> {
+        match self {
+            FriendlyFire::Off => None,
+            FriendlyFire::Reduced => {
+                Some((damage as f32 * constants::FRIENDLY_FIRE_REDUCTION) as i16)
+            }
+            FriendlyFire::Full => Some(damage),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct TeamConfig {
+    // Team deathmatch if enabled, otherwise everyone plays for themselves
+    pub enabled: bool,
+    pub friendly_fire: FriendlyFire,
+}
+
+impl Default for TeamConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            friendly_fire: FriendlyFire::Off,
+        }
+    }
+}
+
+/**
+ * Whether two players are on the same team, never true outside of team games
+ */
+pub fn teammates(a: Option<Team>, b: Option<Team>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => a == b,
+        _ => false,
+    }
+}
+
+impl TeamConfig {
+    /**
+     * Damage dealt by a hit, None if the hit should be ignored because it
+     * was on a teammate and friendly fire is off
+     */
+    pub fn damage(&self, friendly: bool, damage: i16) -> Option<i16> {
+        if friendly {
+            self.friendly_fire.scale_damage(damage)
+        } else {
+            Some(damage)
+        }
+    }
+}
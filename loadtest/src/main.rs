@@ -0,0 +1,362 @@
+use std::io::{self, prelude::*};
+use std::net::TcpStream;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rand::Rng;
+
+use libplen::messages::{ClientInput, ClientMessage, MessageReader, ServerMessage};
+use libplen::player::{Color, PlaneType};
+
+const USAGE: &str = "\
+usage: plen-loadtest [options]
+  --address <host:port>   server to connect to, default localhost:4444
+  --clients <n>           number of connections, default 10
+  --duration <seconds>    how long to play, default 30
+  --script <name>         input of every client, default random
+                            random  changes the input every second
+                            circle  flies in circles and keeps shooting
+                            idle    joins and does nothing";
+
+// Time between inputs, the same rate as the game client
+const INPUT_INTERVAL: Duration = Duration::from_millis(10);
+const PING_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Clone, Copy, PartialEq)]
+enum Script {
+    Random,
+    Circle,
+    Idle,
+}
+
+struct Options {
+    address: String,
+    clients: usize,
+    duration: Duration,
+    script: Script,
+}
+
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut options = Options {
+            address: String::from("localhost:4444"),
+            clients: 10,
+            duration: Duration::from_secs(30),
+            script: Script::Random,
+        };
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(format!("Missing value for {}", arg));
+            match arg.as_str() {
+                "--address" => options.address = value()?,
+                "--clients" => {
+                    options.clients = value()?.parse()
+                        .map_err(|_| String::from("The number of clients is a number"))?;
+                }
+                "--duration" => {
+                    let error = || String::from("The duration is a positive number of seconds");
+                    let seconds: f32 = value()?.parse().map_err(|_| error())?;
+                    // Also rejects infinity and durations too long to represent
+                    options.duration = Some(seconds)
+                        .filter(|seconds| *seconds > 0.)
+                        .and_then(|seconds| Duration::try_from_secs_f32(seconds).ok())
+                        .ok_or_else(error)?;
+                }
+                "--script" => {
+                    options.script = match value()?.as_str() {
+                        "random" => Script::Random,
+                        "circle" => Script::Circle,
+                        "idle" => Script::Idle,
+                        other => return Err(format!("Unknown script {}", other)),
+                    };
+                }
+                "--help" => return Err(String::new()),
+                other => return Err(format!("Unknown option {}", other)),
+            }
+        }
+        Ok(options)
+    }
+}
+
+/**
+ * What one connection measured
+ */
+#[derive(Default)]
+struct ConnectionStats {
+    snapshots: u32,
+    bytes_received: u64,
+    bytes_sent: u64,
+    // Round trip times of the pings in seconds
+    latencies: Vec<f32>,
+    duration: f32,
+    error: Option<String>,
+}
+
+impl ConnectionStats {
+    fn snapshot_rate(&self) -> f32 {
+        self.snapshots as f32 / self.duration.max(0.001)
+    }
+
+    fn average_latency(&self) -> Option<f32> {
+        if self.latencies.is_empty() {
+            None
+        } else {
+            Some(self.latencies.iter().sum::<f32>() / self.latencies.len() as f32)
+        }
+    }
+
+    fn max_latency(&self) -> Option<f32> {
+        self.latencies.iter().cloned().fold(None, |max, l| Some(max.unwrap_or(l).max(l)))
+    }
+
+    fn kilobytes_per_second(&self, bytes: u64) -> f32 {
+        bytes as f32 / 1000. / self.duration.max(0.001)
+    }
+}
+
+/**
+ * Writes all of the bytes to the nonblocking stream, waiting while the send
+ * buffer is full
+ */
+fn send_bytes(bytes: &[u8], stream: &mut TcpStream) -> io::Result<()> {
+    let mut start = 0;
+    while start < bytes.len() {
+        match stream.write(&bytes[start..]) {
+            Ok(0) => return Err(io::Error::new(io::ErrorKind::WriteZero, "Connection closed")),
+            Ok(n) => start += n,
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                thread::sleep(Duration::from_millis(1));
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+fn send_message(
+    msg: &ClientMessage,
+    stream: &mut TcpStream,
+    stats: &mut ConnectionStats
+) -> io::Result<()> {
+    let data = bincode::serialize(msg).expect("Failed to encode message");
+    let length = data.len() as u16;
+    send_bytes(&length.to_be_bytes(), stream)?;
+    send_bytes(&data, stream)?;
+    stats.bytes_sent += 2 + data.len() as u64;
+    Ok(())
+}
+
+fn random_plane(rng: &mut impl Rng) -> PlaneType {
+    match rng.gen_range(0, 4) {
+        0 => PlaneType::SukaBlyat,
+        1 => PlaneType::HowdyCowboy,
+        2 => PlaneType::ElPolloRomero,
+        _ => PlaneType::AchtungBlitzKrieg,
+    }
+}
+
+fn random_color(rng: &mut impl Rng) -> Color {
+    match rng.gen_range(0, 5) {
+        0 => Color::Red,
+        1 => Color::Green,
+        2 => Color::Blue,
+        3 => Color::Yellow,
+        _ => Color::Purple,
+    }
+}
+
+fn scripted_input(script: Script, current: &ClientInput, change: bool, rng: &mut impl Rng)
+    -> ClientInput
+{
+    match script {
+        Script::Random if change => ClientInput {
+            x_input: rng.gen_range(-1., 1.),
+            y_input: rng.gen_range(-1., 1.),
+            shooting: rng.gen(),
+            activating_powerup: rng.gen_bool(0.2),
+        },
+        Script::Random => ClientInput { ..*current },
+        Script::Circle => ClientInput {
+            x_input: 0.5,
+            y_input: 1.,
+            shooting: true,
+            activating_powerup: true,
+        },
+        Script::Idle => ClientInput::new(),
+    }
+}
+
+/**
+ * Joins the game and plays until the time is up
+ */
+fn run_connection(index: usize, options: &Options) -> ConnectionStats {
+    let mut stats = ConnectionStats::default();
+    if let Err(e) = play(index, options, &mut stats) {
+        stats.error = Some(e.to_string());
+    }
+    stats
+}
+
+fn play(index: usize, options: &Options, stats: &mut ConnectionStats) -> io::Result<()> {
+    let mut rng = rand::thread_rng();
+    let mut stream = TcpStream::connect(&options.address)?;
+    stream.set_nodelay(true)?;
+    stream.set_nonblocking(true)?;
+    let mut reader = MessageReader::new(stream.try_clone()?);
+
+    send_message(
+        &ClientMessage::JoinGame {
+            name: format!("loadtest {}", index),
+            plane: random_plane(&mut rng),
+            color: random_color(&mut rng),
+        },
+        &mut stream,
+        stats
+    )?;
+
+    let start = Instant::now();
+    let mut input = ClientInput::new();
+    let mut last_input_change = start;
+    let mut last_ping = start;
+    let mut ping_number = 0;
+    let mut pings_in_flight = vec!();
+
+    while start.elapsed() < options.duration {
+        reader.fetch_bytes()?;
+        for message in reader.iter() {
+            stats.bytes_received += 2 + message.len() as u64;
+            match bincode::deserialize(&message) {
+                Ok(ServerMessage::GameState(_)) => stats.snapshots += 1,
+                Ok(ServerMessage::Pong(number)) => {
+                    let sent = pings_in_flight.iter()
+                        .position(|(n, _): &(u64, Instant)| *n == number)
+                        .map(|i| pings_in_flight.remove(i).1);
+                    if let Some(sent) = sent {
+                        stats.latencies.push(sent.elapsed().as_secs_f32());
+                    }
+                }
+                Ok(ServerMessage::YouDied) => {
+                    // Respawning fails until the respawn time is up, which is
+                    // what the game client does as well
+                    send_message(
+                        &ClientMessage::Respawn { plane: random_plane(&mut rng) },
+                        &mut stream,
+                        stats
+                    )?;
+                }
+                Ok(ServerMessage::Kicked(reason)) => {
+                    return Err(io::Error::other(format!("kicked: {}", reason)));
+                }
+                Ok(_) => {}
+                Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+            }
+        }
+
+        let change = last_input_change.elapsed() > Duration::from_secs(1);
+        if change {
+            last_input_change = Instant::now();
+            // Dead planes keep asking to respawn
+            send_message(
+                &ClientMessage::Respawn { plane: random_plane(&mut rng) },
+                &mut stream,
+                stats
+            )?;
+        }
+        input = scripted_input(options.script, &input, change, &mut rng);
+        send_message(&ClientMessage::Input(ClientInput { ..input }), &mut stream, stats)?;
+
+        if last_ping.elapsed() > PING_INTERVAL {
+            last_ping = Instant::now();
+            ping_number += 1;
+            pings_in_flight.push((ping_number, last_ping));
+            send_message(&ClientMessage::Ping(ping_number), &mut stream, stats)?;
+        }
+
+        thread::sleep(INPUT_INTERVAL);
+    }
+
+    stats.duration = start.elapsed().as_secs_f32();
+    Ok(())
+}
+
+fn format_latency(latency: Option<f32>) -> String {
+    latency.map(|l| format!("{:.1}", l * 1000.)).unwrap_or(String::from("-"))
+}
+
+fn print_summary(results: &[ConnectionStats]) {
+    println!("client  snapshots/s  latency ms (avg/max)  down kB/s  up kB/s");
+    for (i, stats) in results.iter().enumerate() {
+        match &stats.error {
+            Some(error) => println!("{:>6}  failed: {}", i, error),
+            None => println!(
+                "{:>6}  {:>11.1}  {:>9} / {:<9}  {:>9.1}  {:>7.1}",
+                i,
+                stats.snapshot_rate(),
+                format_latency(stats.average_latency()),
+                format_latency(stats.max_latency()),
+                stats.kilobytes_per_second(stats.bytes_received),
+                stats.kilobytes_per_second(stats.bytes_sent),
+            ),
+        }
+    }
+
+    let succeeded: Vec<_> = results.iter().filter(|s| s.error.is_none()).collect();
+    println!();
+    println!("{} of {} clients finished", succeeded.len(), results.len());
+    if succeeded.is_empty() {
+        return;
+    }
+    let count = succeeded.len() as f32;
+    let rate = succeeded.iter().map(|s| s.snapshot_rate()).sum::<f32>() / count;
+    let all_latencies: Vec<f32> = succeeded.iter()
+        .flat_map(|s| s.latencies.iter().cloned())
+        .collect();
+    let average_latency = if all_latencies.is_empty() {
+        None
+    } else {
+        Some(all_latencies.iter().sum::<f32>() / all_latencies.len() as f32)
+    };
+    let max_latency = succeeded.iter().filter_map(|s| s.max_latency())
+        .fold(None, |max: Option<f32>, l| Some(max.unwrap_or(l).max(l)));
+    let down: f32 = succeeded.iter().map(|s| s.kilobytes_per_second(s.bytes_received)).sum();
+    let up: f32 = succeeded.iter().map(|s| s.kilobytes_per_second(s.bytes_sent)).sum();
+    println!("average snapshot rate  {:.1}/s", rate);
+    println!(
+        "latency                {} ms average, {} ms max",
+        format_latency(average_latency),
+        format_latency(max_latency)
+    );
+    println!("total bandwidth        {:.1} kB/s down, {:.1} kB/s up", down, up);
+}
+
+fn main() {
+    let options = match Options::parse(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => {
+            if !e.is_empty() {
+                println!("{}", e);
+            }
+            println!("{}", USAGE);
+            std::process::exit(1);
+        }
+    };
+
+    println!(
+        "Connecting {} clients to {} for {:.0}s",
+        options.clients,
+        options.address,
+        options.duration.as_secs_f32()
+    );
+    let options = std::sync::Arc::new(options);
+    let handles: Vec<_> = (0..options.clients)
+        .map(|i| {
+            let options = options.clone();
+            thread::spawn(move || run_connection(i, &options))
+        })
+        .collect();
+    let results: Vec<_> = handles.into_iter()
+        .map(|handle| handle.join().expect("Connection thread panicked"))
+        .collect();
+
+    print_summary(&results);
+}
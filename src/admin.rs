@@ -0,0 +1,155 @@
+use std::io::{self, BufRead};
+use std::sync::mpsc;
+
+use libplen::bot::{Difficulty, Profile};
+
+use crate::bans::{self, BanKind};
+
+#[derive(Clone, Copy)]
+pub enum BotSetting {
+    Difficulty(Difficulty),
+    Profile(Profile),
+}
+
+pub enum AdminCommand {
+    Players,
+    Kick { id: u64, reason: String },
+    Ban { kind: BanKind, target: String, duration: Option<u64>, reason: String },
+    BanId { id: u64, duration: Option<u64>, reason: String },
+    Unban { target: String },
+    Bans,
+    Stats { name: Option<String> },
+    Bots,
+    // Changes one bot, or all of them if there is no id
+    SetBot { id: Option<u64>, setting: BotSetting },
+    Help,
+}
+
+pub const HELP: &str = "\
+players                                   list connected clients
+kick <id> [reason]                        disconnect a client
+ban ip <ip or cidr> [duration] [reason]   ban an address or network
+ban name <name> [duration] [reason]       ban a player name
+ban id <id> [duration] [reason]           ban and kick a connected client by address
+unban <ip, cidr or name>                  remove bans on a target
+bans                                      list active bans
+stats [name]                              show lifetime stats of a player or the top list
+bots                                      list the bots with their difficulty and profile
+bot <id or all> <difficulty or profile>   change bots, easy, normal, hard or brutal and
+                                          dogfighter, sniper, rammer or scavenger
+durations are written like 30m, 12h or 7d, bans without one are permanent";
+
+fn parse_id(word: Option<&str>) -> Result<u64, String> {
+    word.ok_or(String::from("Missing client id"))?
+        .parse()
+        .map_err(|_| String::from("Client ids are numbers, see `players`"))
+}
+
+/**
+ * Splits the remaining words into an optional duration followed by a reason
+ */
+pub fn parse_duration_and_reason<'a>(words: impl Iterator<Item = &'a str>)
+    -> Result<(Option<u64>, String), String>
+{
+    let mut words = words.peekable();
+    let duration = match words.peek() {
+        Some(word) => bans::parse_duration(word)?,
+        None => None,
+    };
+    if duration.is_some() {
+        words.next();
+    }
+    Ok((duration, words.collect::<Vec<_>>().join(" ")))
+}
+
+impl AdminCommand {
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("players") => Ok(AdminCommand::Players),
+            Some("kick") => {
+                let id = parse_id(words.next())?;
+                Ok(AdminCommand::Kick { id, reason: words.collect::<Vec<_>>().join(" ") })
+            }
+            Some("ban") => {
+                let kind = words.next();
+                if kind == Some("id") {
+                    let id = parse_id(words.next())?;
+                    let (duration, reason) = parse_duration_and_reason(words)?;
+                    return Ok(AdminCommand::BanId { id, duration, reason });
+                }
+                let kind = match kind {
+                    Some("ip") => BanKind::Address,
+                    Some("name") => BanKind::Name,
+                    _ => return Err(String::from("Usage: ban <ip|name|id> <target> [duration] [reason]")),
+                };
+                let target = words.next()
+                    .ok_or(String::from("Missing ban target"))?
+                    .to_string();
+                if kind == BanKind::Address {
+                    target.parse::<bans::IpNet>()?;
+                }
+                let (duration, reason) = parse_duration_and_reason(words)?;
+                Ok(AdminCommand::Ban { kind, target, duration, reason })
+            }
+            Some("unban") => {
+                let target = words.collect::<Vec<_>>().join(" ");
+                if target.is_empty() {
+                    return Err(String::from("Usage: unban <ip, cidr or name>"));
+                }
+                Ok(AdminCommand::Unban { target })
+            }
+            Some("bans") => Ok(AdminCommand::Bans),
+            Some("stats") => {
+                let name = words.collect::<Vec<_>>().join(" ");
+                Ok(AdminCommand::Stats { name: if name.is_empty() {None} else {Some(name)} })
+            }
+            Some("bots") => Ok(AdminCommand::Bots),
+            Some("bot") => {
+                let usage = || String::from("Usage: bot <id or all> <difficulty or profile>");
+                let id = match words.next() {
+                    Some("all") => None,
+                    word => Some(parse_id(word)?),
+                };
+                let value = words.next().ok_or_else(usage)?;
+                let setting = value.parse().map(BotSetting::Difficulty)
+                    .or(value.parse().map(BotSetting::Profile))
+                    .map_err(|_: String| usage())?;
+                Ok(AdminCommand::SetBot { id, setting })
+            }
+            Some("help") => Ok(AdminCommand::Help),
+            Some(other) => Err(format!("Unknown command {}, try `help`", other)),
+            None => Err(String::new()),
+        }
+    }
+}
+
+/**
+ * Reads admin commands from stdin on a separate thread
+ */
+pub fn init_admin_console() -> mpsc::Receiver<AdminCommand> {
+    let (tx, rx) = mpsc::channel();
+
+    std::thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(_) => break,
+            };
+            match AdminCommand::parse(&line) {
+                Ok(command) => {
+                    if tx.send(command).is_err() {
+                        break;
+                    }
+                }
+                Err(e) => {
+                    if !e.is_empty() {
+                        println!("{}", e);
+                    }
+                }
+            }
+        }
+    });
+
+    rx
+}
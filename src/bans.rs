@@ -0,0 +1,243 @@
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, prelude::*};
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde_derive::{Serialize, Deserialize};
+
+use libplen::error;
+
+pub fn unix_time() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/**
+ * Parses durations like 30s, 15m, 2h or 7d into seconds. Returns None if
+ * the text is not a duration, and an error if it is too long to end.
+ */
+pub fn parse_duration(text: &str) -> Result<Option<u64>, String> {
+    let (unit_start, _) = match text.char_indices().last() {
+        Some(last) => last,
+        None => return Ok(None),
+    };
+    let (amount, unit) = text.split_at(unit_start);
+    let scale = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 60 * 60 * 24,
+        _ => return Ok(None),
+    };
+    if amount.is_empty() || !amount.bytes().all(|b| b.is_ascii_digit()) {
+        return Ok(None);
+    }
+    amount.parse::<u64>().ok()
+        .and_then(|amount| amount.checked_mul(scale))
+        .filter(|seconds| unix_time().checked_add(*seconds).is_some())
+        .map(Some)
+        .ok_or(format!("The duration {} is too long", text))
+}
+
+/**
+ * An ip address with a prefix length, a single address is a network with
+ * the full prefix length.
+ */
+#[derive(Clone, Copy, PartialEq)]
+pub struct IpNet {
+    address: IpAddr,
+    prefix: u8,
+}
+
+impl IpNet {
+    pub fn contains(&self, address: IpAddr) -> bool {
+        match (self.address, address) {
+            (IpAddr::V4(net), IpAddr::V4(addr)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32)
+                    .unwrap_or(0);
+                u32::from(net) & mask == u32::from(addr) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(addr)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32)
+                    .unwrap_or(0);
+                u128::from(net) & mask == u128::from(addr) & mask
+            }
+            // Clients connecting over ipv4 to a dual stack socket show up
+            // as mapped ipv6 addresses
+            (IpAddr::V4(_), IpAddr::V6(addr)) => {
+                addr.to_ipv4().map(|a| self.contains(IpAddr::V4(a))).unwrap_or(false)
+            }
+            (IpAddr::V6(_), IpAddr::V4(_)) => false,
+        }
+    }
+}
+
+impl FromStr for IpNet {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, String> {
+        let mut parts = text.splitn(2, '/');
+        let address: IpAddr = parts.next().unwrap_or("").parse()
+            .map_err(|_| format!("{} is not a valid ip address", text))?;
+        let max_prefix = if address.is_ipv4() {32} else {128};
+        let prefix = match parts.next() {
+            Some(prefix) => prefix.parse::<u8>().ok()
+                .filter(|p| *p <= max_prefix)
+                .ok_or(format!("{} has an invalid prefix length", text))?,
+            None => max_prefix,
+        };
+        Ok(Self { address, prefix })
+    }
+}
+
+impl fmt::Display for IpNet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.address, self.prefix)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum BanKind {
+    Address,
+    Name,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Ban {
+    pub kind: BanKind,
+    // An ip or cidr network for address bans, a player name for name bans
+    pub target: String,
+    pub reason: String,
+    pub source: String,
+    pub created: u64,
+    pub expires: Option<u64>,
+}
+
+impl Ban {
+    fn is_expired(&self, now: u64) -> bool {
+        self.expires.map(|expires| expires <= now).unwrap_or(false)
+    }
+
+    fn matches_address(&self, address: IpAddr) -> bool {
+        self.kind == BanKind::Address && self.target.parse::<IpNet>()
+            .map(|net| net.contains(address))
+            .unwrap_or(false)
+    }
+
+    fn matches_name(&self, name: &str) -> bool {
+        self.kind == BanKind::Name && self.target.to_lowercase() == name.trim().to_lowercase()
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct BanFile {
+    #[serde(default)]
+    bans: Vec<Ban>,
+}
+
+/**
+ * The list of active bans, kept in sync with the ban file on disk
+ */
+pub struct BanList {
+    path: PathBuf,
+    bans: Vec<Ban>,
+}
+
+impl BanList {
+    pub fn load(path: PathBuf) -> io::Result<Self> {
+        let bans = match fs::read_to_string(&path) {
+            Ok(content) => toml::from_str::<BanFile>(&content)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+                .bans,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => vec!(),
+            Err(e) => return Err(e),
+        };
+        let mut result = Self { path, bans };
+        result.remove_expired();
+        Ok(result)
+    }
+
+    fn save(&self) {
+        let file = BanFile { bans: self.bans.clone() };
+        let result = toml::to_string(&file)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+            .and_then(|content| fs::write(&self.path, content));
+        if let Err(e) = result {
+            error!("Could not save ban list to {}: {}", self.path.display(), e);
+        }
+    }
+
+    fn remove_expired(&mut self) {
+        let now = unix_time();
+        let count = self.bans.len();
+        self.bans.retain(|ban| !ban.is_expired(now));
+        if self.bans.len() != count {
+            self.save();
+        }
+    }
+
+    pub fn add(&mut self, ban: Ban) {
+        self.bans.push(ban);
+        self.save();
+    }
+
+    /**
+     * Removes all bans with the given target, returns true if any were removed
+     */
+    pub fn remove(&mut self, target: &str) -> bool {
+        let count = self.bans.len();
+        self.bans.retain(|ban| ban.target.to_lowercase() != target.to_lowercase());
+        let removed = self.bans.len() != count;
+        if removed {
+            self.save();
+        }
+        removed
+    }
+
+    pub fn bans(&mut self) -> &[Ban] {
+        self.remove_expired();
+        &self.bans
+    }
+
+    pub fn find_address_ban(&mut self, address: IpAddr) -> Option<&Ban> {
+        self.remove_expired();
+        self.bans.iter().find(|ban| ban.matches_address(address))
+    }
+
+    pub fn find_name_ban(&mut self, name: &str) -> Option<&Ban> {
+        self.remove_expired();
+        self.bans.iter().find(|ban| ban.matches_name(name))
+    }
+}
+
+/**
+ * Append only log of all kicks and bans
+ */
+pub struct ModerationLog {
+    path: PathBuf,
+}
+
+impl ModerationLog {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    pub fn record(&self, source: &str, action: &str, target: &str, reason: &str) {
+        let line = format!(
+            "{} source={:?} action={} target={:?} reason={:?}\n",
+            unix_time(), source, action, target, reason
+        );
+        let result = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| file.write_all(line.as_bytes()));
+        if let Err(e) = result {
+            error!("Could not write to moderation log {}: {}", self.path.display(), e);
+        }
+    }
+}
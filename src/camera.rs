@@ -0,0 +1,77 @@
+use sdl2::keyboard::{KeyboardState, Scancode};
+
+use libplen::constants;
+use libplen::gamestate::GameState;
+use libplen::math::{self, Vec2, vec2};
+
+/**
+ * The camera of spectators and replays, which either follows a player or
+ * is flown freely over the map
+ */
+pub struct SpectatorCamera {
+    pub position: Vec2,
+    // The player to follow, the camera flies freely if None
+    pub following: Option<u64>,
+}
+
+impl SpectatorCamera {
+    pub fn new() -> Self {
+        Self {
+            position: vec2(0., 0.),
+            following: None,
+        }
+    }
+
+    /**
+     * Follows the player after the current one, or the first one when flying
+     * freely
+     */
+    pub fn follow_next_player(&mut self, game_state: &GameState) {
+        let mut ids: Vec<_> = game_state.players.iter().map(|p| p.id).collect();
+        ids.sort();
+        self.following = match self.following {
+            Some(current) => ids.iter().find(|id| **id > current).or(ids.first()).cloned(),
+            None => ids.first().cloned(),
+        };
+    }
+
+    pub fn update(
+        &mut self,
+        delta_time: f32,
+        game_state: &GameState,
+        // None while the keys are used for something else
+        keyboard_state: Option<&KeyboardState>
+    ) {
+        // Dead players disappear from the game state until they respawn
+        let followed = self.following.and_then(|id| game_state.get_player_by_id(id));
+        if let Some(player) = followed {
+            self.position = player.position;
+            return;
+        }
+        if self.following.is_some() {
+            self.follow_next_player(game_state);
+            return;
+        }
+
+        let keyboard_state = match keyboard_state {
+            Some(keyboard_state) => keyboard_state,
+            None => return,
+        };
+        let mut direction = vec2(0., 0.);
+        if keyboard_state.is_scancode_pressed(Scancode::W) {
+            direction.y -= 1.;
+        }
+        if keyboard_state.is_scancode_pressed(Scancode::S) {
+            direction.y += 1.;
+        }
+        if keyboard_state.is_scancode_pressed(Scancode::A) {
+            direction.x -= 1.;
+        }
+        if keyboard_state.is_scancode_pressed(Scancode::D) {
+            direction.x += 1.;
+        }
+        let position = self.position
+            + direction * constants::SPECTATOR_CAMERA_SPEED * delta_time;
+        self.position = math::wrap_around(position);
+    }
+}
@@ -0,0 +1,192 @@
+use sdl2::keyboard::Keycode;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+
+use libplen::constants;
+use libplen::gamestate::GameState;
+use libplen::math::vec2;
+
+use crate::assets::Assets;
+use crate::rendering;
+
+// Lines shown at most, older ones are dropped
+const MAX_LINES: usize = 8;
+const LINE_HEIGHT: f32 = 24.;
+// Below the killfeed
+const TOP: f32 = 150.;
+// Team messages are sent as this command
+const TEAM_COMMAND: &str = "/team ";
+
+struct ChatLine {
+    sender: Option<u64>,
+    name: String,
+    text: String,
+    age: f32,
+}
+
+pub enum ChatAction {
+    None,
+    Send(String),
+}
+
+/**
+ * The recent chat messages and the message being typed
+ */
+pub struct Chat {
+    lines: Vec<ChatLine>,
+    // Some while typing, the flight controls are ignored meanwhile
+    input: Option<String>,
+    // Whether the message being typed only goes to the team
+    team: bool,
+}
+
+impl Chat {
+    pub fn new() -> Self {
+        Self {
+            lines: vec!(),
+            input: None,
+            team: false,
+        }
+    }
+
+    pub fn is_typing(&self) -> bool {
+        self.input.is_some()
+    }
+
+    pub fn open(&mut self, team: bool) {
+        self.input = Some(String::new());
+        self.team = team;
+    }
+
+    pub fn add_message(&mut self, sender: Option<u64>, name: String, text: String) {
+        self.lines.push(ChatLine { sender, name, text, age: 0. });
+        if self.lines.len() > MAX_LINES {
+            self.lines.remove(0);
+        }
+    }
+
+    pub fn update(&mut self, delta_time: f32) {
+        for line in &mut self.lines {
+            line.age += delta_time;
+        }
+    }
+
+    pub fn text_input(&mut self, text: &str) {
+        if let Some(input) = &mut self.input {
+            let max_length = if self.team {
+                constants::CHAT_MAX_LENGTH - TEAM_COMMAND.len()
+            } else {
+                constants::CHAT_MAX_LENGTH
+            };
+            let room = max_length.saturating_sub(input.chars().count());
+            input.extend(text.chars().take(room));
+        }
+    }
+
+    /**
+     * Handles a key while typing. Enter sends the message and escape throws
+     * it away.
+     */
+    pub fn key_down_event(&mut self, keycode: Keycode) -> ChatAction {
+        match keycode {
+            Keycode::Return | Keycode::KpEnter => {
+                let text = self.input.take().unwrap_or_default();
+                if text.trim().is_empty() {
+                    return ChatAction::None;
+                }
+                return if self.team {
+                    ChatAction::Send(format!("{}{}", TEAM_COMMAND, text))
+                } else {
+                    ChatAction::Send(text)
+                };
+            }
+            Keycode::Escape => self.input = None,
+            Keycode::Backspace => {
+                if let Some(input) = &mut self.input {
+                    input.pop();
+                }
+            }
+            _ => {}
+        }
+        ChatAction::None
+    }
+
+    /**
+     * Draws the messages under the killfeed with the names in the color of
+     * the planes of their senders. Old messages fade away, but are all shown
+     * while typing.
+     */
+    pub fn draw(
+        &self,
+        canvas: &mut Canvas<Window>,
+        assets: &Assets,
+        game_state: &GameState,
+    ) -> Result<(), String> {
+        let right = canvas.logical_size().0 as f32 - 10.;
+        let mut y = TOP;
+        for line in &self.lines {
+            let alpha = if self.is_typing() {
+                1.
+            } else {
+                ((constants::CHAT_DURATION - line.age) / constants::CHAT_FADE_TIME).min(1.)
+            };
+            if alpha <= 0. {
+                continue;
+            }
+            let alpha = (alpha * 255.) as u8;
+
+            let (name, name_color) = match line.sender {
+                Some(id) => {
+                    let color = game_state.get_player_by_id(id)
+                        .or(game_state.get_dead_player_by_id(id).map(|dead| &dead.player))
+                        .map(|player| player.color.rgb())
+                        .unwrap_or((180, 180, 180));
+                    (format!("{}: ", line.name), color)
+                }
+                // Notices from the server have no name
+                None => (String::new(), (255, 220, 100)),
+            };
+            let text_color = if line.sender.is_some() {(255, 255, 255)} else {name_color};
+
+            let text_width =
+                draw_right_aligned(canvas, assets, &line.text, text_color, alpha, right, y)?;
+            draw_right_aligned(canvas, assets, &name, name_color, alpha, right - text_width, y)?;
+            y += LINE_HEIGHT;
+        }
+
+        if let Some(input) = &self.input {
+            let prompt = if self.team {"Team"} else {"Say"};
+            draw_right_aligned(
+                canvas, assets, &format!("{}: {}_", prompt, input), (255, 255, 255), 255, right, y
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/**
+ * Draws text that ends at x, returning its width
+ */
+fn draw_right_aligned(
+    canvas: &mut Canvas<Window>,
+    assets: &Assets,
+    text: &str,
+    color: (u8, u8, u8),
+    alpha: u8,
+    x: f32,
+    y: f32,
+) -> Result<f32, String> {
+    if text.is_empty() {
+        return Ok(0.);
+    }
+    let surface = assets.font.render(text)
+        .blended(color)
+        .map_err(|e| e.to_string())?;
+    let texture_creator = canvas.texture_creator();
+    let mut texture = texture_creator.create_texture_from_surface(surface)
+        .map_err(|e| e.to_string())?;
+    texture.set_alpha_mod(alpha);
+    let width = texture.query().width as f32;
+    rendering::draw_texture(canvas, &texture, vec2(x - width, y))?;
+    Ok(width)
+}
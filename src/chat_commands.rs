@@ -0,0 +1,126 @@
+use libplen::gamemode::ModeName;
+
+use crate::admin::parse_duration_and_reason;
+
+/**
+ * What a client is allowed to do in chat. Admins log in with the admin
+ * password from the config.
+ */
+#[derive(Clone, Copy, PartialEq, PartialOrd, Debug)]
+pub enum Role {
+    Player,
+    Admin,
+}
+
+/**
+ * A chat message starting with a slash. Commands that name a player keep the
+ * rest of the message, since names can have spaces in them.
+ */
+pub enum ChatCommand {
+    Help,
+    Whisper { rest: String },
+    Team { text: String },
+    // Ignores a player, or stops ignoring them
+    Ignore { name: String },
+    Stats { name: Option<String> },
+    Ping,
+    Vote(VoteCommand),
+    Login { password: String },
+    Kick { rest: String },
+    Ban { rest: String },
+}
+
+pub enum VoteCommand {
+    Kick { name: String },
+    Mode(ModeName),
+    Restart,
+    Bots(usize),
+    // Yes or no to the running vote, for players without F1 and F2
+    Cast(bool),
+}
+
+const VOTE_USAGE: &str = "Usage: /vote kick <name>, mode <mode>, restart, bots <count>, yes or no";
+
+impl VoteCommand {
+    fn parse(rest: &str) -> Result<Self, String> {
+        let mut words = rest.split_whitespace();
+        let kind = words.next().unwrap_or("");
+        let argument = rest_of(words);
+        match (kind, argument.as_str()) {
+            ("kick", "") => Err(String::from("Usage: /vote kick <name>")),
+            ("kick", name) => Ok(VoteCommand::Kick { name: name.to_string() }),
+            ("mode", mode) => mode.parse().map(VoteCommand::Mode),
+            ("restart", "") => Ok(VoteCommand::Restart),
+            ("bots", count) => count.parse()
+                .map(VoteCommand::Bots)
+                .map_err(|_| String::from("Usage: /vote bots <count>")),
+            ("yes", "") => Ok(VoteCommand::Cast(true)),
+            ("no", "") => Ok(VoteCommand::Cast(false)),
+            _ => Err(String::from(VOTE_USAGE)),
+        }
+    }
+}
+
+pub const HELP: &[(&str, Role)] = &[
+    ("/w <name> <message>       whisper to a player", Role::Player),
+    ("/team <message>           talk to your team", Role::Player),
+    ("/ignore <name>            hide or show messages from a player", Role::Player),
+    ("/stats [name]             lifetime stats of you or a player", Role::Player),
+    ("/ping                     time to the server and back", Role::Player),
+    ("/vote kick <name>         start a vote to kick a player, or /votekick", Role::Player),
+    ("/vote mode <mode>         vote for ffa, ctf, koth, battle_royale or race", Role::Player),
+    ("/vote restart             vote to restart the round", Role::Player),
+    ("/vote bots <count>        vote to fill the game with bots, 0 for none", Role::Player),
+    ("/vote yes, /vote no       vote like F1 and F2 do", Role::Player),
+    ("/login <password>         use the admin commands", Role::Player),
+    ("/kick <name> [reason]     disconnect a player", Role::Admin),
+    ("/ban <name> [duration] [reason]   ban a player name", Role::Admin),
+];
+
+fn rest_of<'a>(words: impl Iterator<Item = &'a str>) -> String {
+    words.collect::<Vec<_>>().join(" ")
+}
+
+impl ChatCommand {
+    /**
+     * Parses a message without the leading slash
+     */
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let mut words = line.split_whitespace();
+        let command = words.next().unwrap_or("");
+        let rest = rest_of(words);
+        let required = |usage: &str| {
+            if rest.is_empty() {Err(format!("Usage: {}", usage))} else {Ok(rest.clone())}
+        };
+        match command {
+            "help" => Ok(ChatCommand::Help),
+            "w" | "whisper" => Ok(ChatCommand::Whisper { rest: required("/w <name> <message>")? }),
+            "team" | "t" => Ok(ChatCommand::Team { text: required("/team <message>")? }),
+            "ignore" => Ok(ChatCommand::Ignore { name: required("/ignore <name>")? }),
+            "stats" => Ok(ChatCommand::Stats { name: required("").ok() }),
+            "ping" => Ok(ChatCommand::Ping),
+            "vote" => Ok(ChatCommand::Vote(VoteCommand::parse(&rest)?)),
+            "votekick" => Ok(ChatCommand::Vote(VoteCommand::Kick {
+                name: required("/votekick <name>")?
+            })),
+            "login" => Ok(ChatCommand::Login { password: required("/login <password>")? }),
+            "kick" => Ok(ChatCommand::Kick { rest: required("/kick <name> [reason]")? }),
+            "ban" => Ok(ChatCommand::Ban { rest: required("/ban <name> [duration] [reason]")? }),
+            other => Err(format!("Unknown command /{}, try /help", other)),
+        }
+    }
+
+    pub fn required_role(&self) -> Role {
+        match self {
+            ChatCommand::Kick { .. } | ChatCommand::Ban { .. } => Role::Admin,
+            _ => Role::Player,
+        }
+    }
+}
+
+/**
+ * Splits the duration and reason after the name of a ban
+ */
+pub fn parse_ban(rest: &str) -> Result<(Option<u64>, String), String> {
+    parse_duration_and_reason(rest.split_whitespace())
+}
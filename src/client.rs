@@ -1,10 +1,15 @@
 mod assets;
+mod camera;
+mod chat;
+mod hud;
 mod map;
 mod menu;
 mod rendering;
+mod replay_player;
 
 use std::io::prelude::*;
 use std::net::TcpStream;
+use std::path::{Path, PathBuf};
 use std::time::Instant;
 
 use sdl2::render::Canvas;
@@ -14,18 +19,30 @@ use sdl2::render::BlendMode;
 use sdl2::keyboard::{Keycode, Scancode};
 
 use libplen::messages::{
+    ChatChannel,
     ClientMessage,
     ClientInput,
     MessageReader,
     ServerMessage,
-    SoundEffect
+    SoundEffect,
+    VoteStatus
 };
 use libplen::gamestate;
 use libplen::constants;
 use libplen::hurricane;
 use libplen::math::{Vec2, vec2};
+use libplen::player::PlaneType;
+use libplen::stats::LifetimeStats;
+use libplen::log::{self, LogConfig};
+use libplen::{error, info, warn};
 use assets::Assets;
+use camera::SpectatorCamera;
+use chat::{Chat, ChatAction};
 use menu::MenuState;
+use replay_player::ReplayPlayer;
+
+// The players with the most kills, and the stats of this player
+type LifetimeStatsTable = (Vec<(String, LifetimeStats)>, Option<LifetimeStats>);
 
 fn send_client_message(msg: &ClientMessage, stream: &mut TcpStream) {
     let data = bincode::serialize(msg).expect("Failed to encode message");
@@ -36,8 +53,40 @@ fn send_client_message(msg: &ClientMessage, stream: &mut TcpStream) {
         .expect("Failed to send message to server");
 }
 
+/**
+ * Plays a sound from the server, along with the effects that go with it
+ */
+fn play_sound_effect(sound: SoundEffect, pos: Vec2, assets: &Assets, map: &mut map::Map) {
+    fn play_sound(soundeffect: &sdl2::mixer::Chunk) {
+        if let Err(e) = sdl2::mixer::Channel::all().play(
+            soundeffect, 0
+        ) {
+            warn!("SDL mixer error: {}", e);
+        }
+    }
+
+    match sound {
+        SoundEffect::Powerup => {
+            play_sound(&assets.powerup);
+        }
+        SoundEffect::Gun => {
+            play_sound(&assets.gun);
+        }
+        SoundEffect::Explosion => {
+            play_sound(&assets.explosion);
+            map.add_explosion(pos);
+        }
+        SoundEffect::LaserCharge => {
+            play_sound(&assets.laser_charge_sound);
+        }
+        SoundEffect::LaserFire => {
+            play_sound(&assets.laser_fire_sound);
+        }
+    }
+}
+
 #[derive(PartialEq)]
-enum StateResult { Continue, GotoNext }
+enum StateResult { Continue, GotoNext, Disconnected }
 
 struct MainState {
     my_id: u64,
@@ -48,22 +97,53 @@ struct MainState {
     powerup_rotation: f32,
     hit_effect_timer: f32,
     dead: bool,
+    // The plane to respawn in
+    respawn_plane: PlaneType,
+    show_scoreboard: bool,
+    show_lifetime_stats: bool,
+    lifetime_stats: Option<LifetimeStatsTable>,
+    spectating: bool,
+    spectator_camera: SpectatorCamera,
+    chat: Chat,
+    // When /ping was sent, to show how long the answer took
+    ping_sent: Option<Instant>,
+    // The running vote and when it was received, to count down the time left
+    vote: Option<(VoteStatus, Instant)>,
 }
 
 impl MainState {
-    fn new(my_id: u64) -> MainState {
+    fn new(my_id: u64, plane: PlaneType, spectating: bool) -> MainState {
+        let mut map = map::Map::new();
+        map.full_info = spectating;
         MainState {
             my_id,
             camera_position: vec2(0., 0.),
             game_state: gamestate::GameState::new(),
-            map: map::Map::new(),
+            map,
             last_time: Instant::now(),
             powerup_rotation: 0.,
             hit_effect_timer: 0.,
             dead: false,
+            respawn_plane: plane,
+            show_scoreboard: false,
+            show_lifetime_stats: false,
+            lifetime_stats: None,
+            spectating,
+            spectator_camera: SpectatorCamera::new(),
+            chat: Chat::new(),
+            ping_sent: None,
+            vote: None,
         }
     }
 
+    /**
+     * The player whose view is shown, which is a followed player when
+     * spectating
+     */
+    fn viewed_id(&self) -> u64 {
+        self.spectator_camera.following.unwrap_or(self.my_id)
+    }
+
     fn update(
         &mut self,
         assets: &Assets,
@@ -85,72 +165,104 @@ impl MainState {
             match bincode::deserialize(&message).unwrap() {
                 ServerMessage::AssignId(_) => {panic!("Got new ID after intialisation")}
                 ServerMessage::GameState(state) => {
-                    self.game_state = state
+                    self.game_state = *state
                 },
                 ServerMessage::PlaySound(sound, pos) => {
-                    fn play_sound(soundeffect: &sdl2::mixer::Chunk) {
-                        if let Err(e) = sdl2::mixer::Channel::all().play(
-                            soundeffect, 0
-                        ) {
-                            println!("SDL mixer error: {}", e);
-                        }
-                    }
-
-                    match sound {
-                        SoundEffect::Powerup => {
-                            play_sound(&assets.powerup);
-                        }
-                        SoundEffect::Gun => {
-                            play_sound(&assets.gun);
-                        }
-                        SoundEffect::Explosion => {
-                            play_sound(&assets.explosion);
-                            self.map.add_explosion(pos);
-                        }
-                        SoundEffect::LaserCharge => {
-                            play_sound(&assets.laser_charge_sound);
-                        }
-                        SoundEffect::LaserFire => {
-                            play_sound(&assets.laser_fire_sound);
-                        }
-                    }
+                    play_sound_effect(sound, pos, assets, &mut self.map);
                 }
                 ServerMessage::YouDied => {
                     self.dead = true;
                 }
+                ServerMessage::Kicked(reason) => {
+                    warn!("Kicked from the server: {}", reason);
+                    return StateResult::Disconnected;
+                }
+                ServerMessage::LifetimeStats { top, mine } => {
+                    self.lifetime_stats = Some((top, mine));
+                }
                 ServerMessage::PlayerHit(id) => {
                     // TODO handle if it's someone elses id, for example
                     // for sound effects and stuff
-                    if id == self.my_id {
+                    if id == self.viewed_id() {
                         self.start_hit_sequence();
                     }
                 }
+                // The answer to /ping in chat
+                ServerMessage::Pong(_) => {
+                    if let Some(sent) = self.ping_sent.take() {
+                        let text = format!("Ping: {} ms", sent.elapsed().as_millis());
+                        self.chat.add_message(None, String::new(), text);
+                    }
+                }
+                ServerMessage::Chat { sender, name, channel, text, .. } => {
+                    let name = match channel {
+                        ChatChannel::All => name,
+                        ChatChannel::Team => format!("{} (team)", name),
+                        ChatChannel::Whisper { to } if sender == Some(self.my_id) => {
+                            format!("To {}", to)
+                        }
+                        ChatChannel::Whisper { .. } => format!("{} (whisper)", name),
+                    };
+                    self.chat.add_message(sender, name, text);
+                }
+                ServerMessage::Vote(status) => {
+                    self.vote = status.map(|status| (status, Instant::now()));
+                }
             }
         }
+        self.chat.update(elapsed.as_secs_f32());
+
+        // Keys pressed while typing a message don't fly the plane
+        let keyboard_state = if self.chat.is_typing() {None} else {Some(keyboard_state)};
+
+        if self.spectating {
+            self.spectator_camera.update(elapsed.as_secs_f32(), &self.game_state, keyboard_state);
+            self.camera_position = self.spectator_camera.position;
+            self.map.update(elapsed.as_secs_f32(), &self.game_state, self.viewed_id());
+            self.show_scoreboard = keyboard_state
+                .map(|keys| keys.is_scancode_pressed(Scancode::Tab))
+                .unwrap_or(false);
+            self.powerup_rotation += constants::POWERUP_SPEED * elapsed.as_secs_f32();
+            return StateResult::Continue;
+        }
 
-        let mut input = ClientInput::new();
-        if keyboard_state.is_scancode_pressed(Scancode::W) {
-            input.y_input += 1.0;
+        // The server puts us back in the game once we respawn
+        let alive = self.game_state.get_player_by_id(self.my_id)
+            .map(|p| p.health > 0)
+            .unwrap_or(false);
+        if self.dead && alive {
+            self.dead = false;
         }
-        if keyboard_state.is_scancode_pressed(Scancode::S) {
-            input.y_input -= 1.0;
+        // Players joining a battle royale match wait with the dead players
+        if self.game_state.get_dead_player_by_id(self.my_id).is_some() {
+            self.dead = true;
         }
 
-        if keyboard_state.is_scancode_pressed(Scancode::A) {
-            input.x_input -= 1.0;
-        } 
-        if keyboard_state.is_scancode_pressed(Scancode::D) {
-            input.x_input += 1.0;
-        }
+        let mut input = ClientInput::new();
+        self.show_scoreboard = false;
+        if let Some(keyboard_state) = keyboard_state {
+            if keyboard_state.is_scancode_pressed(Scancode::W) {
+                input.y_input += 1.0;
+            }
+            if keyboard_state.is_scancode_pressed(Scancode::S) {
+                input.y_input -= 1.0;
+            }
 
-        if self.dead && keyboard_state.is_scancode_pressed(Scancode::Return) {
-            return StateResult::GotoNext;
+            if keyboard_state.is_scancode_pressed(Scancode::A) {
+                input.x_input -= 1.0;
+            }
+            if keyboard_state.is_scancode_pressed(Scancode::D) {
+                input.x_input += 1.0;
+            }
+
+            self.show_scoreboard = keyboard_state.is_scancode_pressed(Scancode::Tab);
+
+            input.shooting = keyboard_state.is_scancode_pressed(Scancode::Space);
+            input.activating_powerup = keyboard_state.is_scancode_pressed(Scancode::E);
         }
 
         self.map.update(elapsed.as_secs_f32(), &self.game_state, self.my_id);
 
-        input.shooting = keyboard_state.is_scancode_pressed(Scancode::Space);
-        input.activating_powerup = keyboard_state.is_scancode_pressed(Scancode::E);
         let input_message = ClientMessage::Input(input);
         send_client_message(&input_message, &mut server_reader.stream);
 
@@ -159,13 +271,60 @@ impl MainState {
         StateResult::Continue
     }
 
+    fn key_down_event(&mut self, keycode: Keycode, stream: &mut TcpStream) -> StateResult {
+        if self.chat.is_typing() {
+            if let ChatAction::Send(text) = self.chat.key_down_event(keycode) {
+                if text.trim() == "/ping" {
+                    self.ping_sent = Some(Instant::now());
+                }
+                send_client_message(&ClientMessage::Chat(text), stream);
+            }
+            return StateResult::Continue;
+        }
+
+        match keycode {
+            Keycode::T => self.chat.open(false),
+            Keycode::Y => self.chat.open(true),
+            Keycode::F1 | Keycode::F2 if self.vote.is_some() => {
+                send_client_message(&ClientMessage::Vote(keycode == Keycode::F1), stream);
+            }
+            Keycode::Space if self.spectating => {
+                self.spectator_camera.follow_next_player(&self.game_state);
+            }
+            Keycode::F if self.spectating => {
+                self.spectator_camera.following = None;
+            }
+            Keycode::Escape if self.spectating => {
+                return StateResult::GotoNext;
+            }
+            Keycode::L => {
+                self.show_lifetime_stats = !self.show_lifetime_stats;
+                if self.show_lifetime_stats {
+                    send_client_message(&ClientMessage::RequestLifetimeStats, stream);
+                }
+            }
+            Keycode::Return if self.dead => {
+                send_client_message(&ClientMessage::Respawn { plane: self.respawn_plane }, stream);
+            }
+            Keycode::Escape if self.dead => {
+                return StateResult::GotoNext;
+            }
+            Keycode::Num1 | Keycode::Num2 | Keycode::Num3 | Keycode::Num4 if self.dead => {
+                let index = keycode as usize - Keycode::Num1 as usize;
+                self.respawn_plane = menu::PLANES[index];
+            }
+            _ => {}
+        }
+        StateResult::Continue
+    }
+
     fn draw(&mut self, canvas: &mut Canvas<Window>, assets: &mut Assets) -> Result<(), String> {
         if let Some(my_player) = self.game_state.get_player_by_id(self.my_id) {
             self.camera_position = my_player.position;
         }
 
         self.map.draw(
-            self.my_id,
+            self.viewed_id(),
             canvas,
             self.camera_position,
             &self.game_state,
@@ -175,6 +334,31 @@ impl MainState {
             &self.game_state.hurricane
         )?;
 
+        hud::draw_round_status(canvas, assets, &self.game_state.round)?;
+        hud::draw_team_scores(canvas, assets, &self.game_state)?;
+        hud::draw_control_bar(canvas, assets, &self.game_state)?;
+        hud::draw_battle_royale(canvas, assets, &self.game_state)?;
+        hud::draw_race(canvas, assets, &self.game_state, self.viewed_id())?;
+        if self.spectating {
+            hud::draw_spectator(
+                canvas, assets, &self.game_state, self.spectator_camera.following,
+                "Space: next player   F: free camera   WASD: fly   Escape: menu"
+            )?;
+        }
+
+        self.chat.draw(canvas, assets, &self.game_state)?;
+        if let Some((status, received)) = &self.vote {
+            hud::draw_vote(canvas, assets, status, received.elapsed().as_secs_f32())?;
+        }
+
+        if self.show_scoreboard {
+            hud::draw_scoreboard(canvas, assets, &self.game_state, self.my_id)?;
+        } else if self.show_lifetime_stats {
+            if let Some((top, mine)) = &self.lifetime_stats {
+                hud::draw_lifetime_stats(canvas, assets, top, mine.as_ref())?;
+            }
+        }
+
         if self.dead {
             let (width, height) = canvas.logical_size();
             rendering::draw_texture_centered(
@@ -182,6 +366,14 @@ impl MainState {
                 &assets.end_background,
                 vec2(width as f32 * 0.5, height as f32 * 0.3)
             ).unwrap();
+
+            hud::draw_respawn_prompt(
+                canvas,
+                assets,
+                self.game_state.get_dead_player_by_id(self.my_id),
+                self.respawn_plane,
+                !self.game_state.respawn_allowed()
+            )?;
         }
 
         Ok(())
@@ -206,28 +398,78 @@ impl MainState {
     }
 }
 
-pub fn main() -> Result<(), String> {
-    let host = std::env::var("SERVER")
-        .unwrap_or(String::from("localhost:4444"));
-    let stream = TcpStream::connect(host).expect("Could not connect to server");
-    println!("Connected to server");
+/**
+ * Plays a replay until it is closed. Messages from the server are ignored
+ * meanwhile, so that it doesn't freeze.
+ */
+fn run_replay(
+    path: &Path,
+    canvas: &mut Canvas<Window>,
+    assets: &mut Assets,
+    event_pump: &mut sdl2::EventPump,
+    mut server_reader: Option<&mut MessageReader>,
+) -> Result<StateResult, String> {
+    let mut player = match ReplayPlayer::load(path) {
+        Ok(player) => player,
+        Err(e) => {
+            error!("Could not open replay {}: {}", path.display(), e);
+            return Ok(StateResult::GotoNext);
+        }
+    };
 
-    stream.set_nonblocking(true).expect("Could not set socket as nonblocking");
-    let mut reader = MessageReader::new(stream);
+    loop {
+        for event in event_pump.poll_iter() {
+            match event {
+                Event::Quit{..} => return Ok(StateResult::Disconnected),
+                Event::KeyDown {keycode: Some(kc), ..} => {
+                    let result = player.key_down_event(kc);
+                    if result == StateResult::GotoNext {
+                        return Ok(result);
+                    }
+                }
+                Event::MouseButtonDown {x, y, ..} => {
+                    player.mouse_button_down_event(x as f32, y as f32, canvas);
+                }
+                _ => {}
+            }
+        }
+        rendering::setup_coordinates(canvas)?;
 
-    let msg = loop {
-        reader.fetch_bytes().unwrap();
-        if let Some(msg) = reader.iter().next() {
-            break bincode::deserialize(&msg).unwrap();
+        if let Some(reader) = &mut server_reader {
+            reader.fetch_bytes().unwrap();
+            for message in reader.iter() {
+                if let Ok(ServerMessage::Kicked(reason)) = bincode::deserialize(&message) {
+                    warn!("Kicked from the server: {}", reason);
+                    return Ok(StateResult::Disconnected);
+                }
+            }
         }
-    };
 
-    let my_id = if let ServerMessage::AssignId(id) = msg {
-        println!("Received the id {}", id);
-        id
-    } else {
-        panic!("Expected to get an id from server")
-    };
+        canvas.set_draw_color(sdl2::pixels::Color::RGB(25, 25, 25));
+        canvas.clear();
+
+        if player.update(assets, &event_pump.keyboard_state()) == StateResult::GotoNext {
+            return Ok(StateResult::GotoNext);
+        }
+        player.draw(canvas, assets)?;
+
+        canvas.present();
+    }
+}
+
+pub fn main() -> Result<(), String> {
+    // The client has no config file, PLEN_LOG and PLEN_LOG_FORMAT set the
+    // level and format
+    log::init(&LogConfig::default());
+
+    let mut args = std::env::args().skip(1);
+    let mut replay_path = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--replay" => replay_path = args.next().map(PathBuf::from),
+            other => warn!("Unknown argument {}", other),
+        }
+    }
 
     let sdl = sdl2::init().expect("Could not initialize SDL");
     let video_subsystem = sdl.video().expect("Could not initialize SDL video");
@@ -265,6 +507,35 @@ pub fn main() -> Result<(), String> {
 
     let mut event_pump = sdl.event_pump().expect("Could not get event pump");
 
+    // Replays from the command line are watched without a server
+    if let Some(path) = replay_path {
+        run_replay(&path, &mut canvas, &mut assets, &mut event_pump, None)?;
+        return Ok(());
+    }
+
+    let host = std::env::var("SERVER")
+        .unwrap_or(String::from("localhost:4444"));
+    let stream = TcpStream::connect(&host).expect("Could not connect to server");
+    info!(address = host; "Connected to server");
+
+    stream.set_nonblocking(true).expect("Could not set socket as nonblocking");
+    let mut reader = MessageReader::new(stream);
+
+    let msg = loop {
+        reader.fetch_bytes().unwrap();
+        if let Some(msg) = reader.iter().next() {
+            break bincode::deserialize(&msg).unwrap();
+        }
+    };
+
+    let my_id = if let ServerMessage::AssignId(id) = msg {
+        info!(client = id; "Received an id from the server");
+        id
+    } else {
+        panic!("Expected to get an id from server")
+    };
+
+
     'mainloop: loop {
         let menu_state = &mut MenuState::new();
 
@@ -277,14 +548,23 @@ pub fn main() -> Result<(), String> {
             for event in event_pump.poll_iter() {
                 match event {
                     Event::Quit{..} => break 'mainloop,
-                    Event::KeyDown {keycode: Some(kc), ..} => {
+                    Event::KeyDown {keycode: Some(kc), repeat, ..} => {
                         match kc {
                             Keycode::Return => {
                                 break 'menuloop;
                             }
+                            Keycode::Tab => {
+                                menu_state.spectate = true;
+                                break 'menuloop;
+                            }
+                            Keycode::F1 if menu_state.selected_replay().is_some() => {
+                                menu_state.watch_replay = true;
+                                break 'menuloop;
+                            }
                             Keycode::Backspace => {
                                 menu_state.name.pop();
                             }
+                            _ if !repeat => menu_state.key_down_event(kc),
                             _ => {}
                         }
                     }
@@ -303,7 +583,11 @@ pub fn main() -> Result<(), String> {
 
             // Ignore all messages so we don't freeze the server
             reader.fetch_bytes().unwrap();
-            for _ in reader.iter() {
+            for message in reader.iter() {
+                if let Ok(ServerMessage::Kicked(reason)) = bincode::deserialize(&message) {
+                    warn!("Kicked from the server: {}", reason);
+                    break 'mainloop;
+                }
             }
 
             menu_state.update();
@@ -316,23 +600,55 @@ pub fn main() -> Result<(), String> {
         plane_selection = menu_state.plane_selection;
         name = menu_state.name.clone();
 
-        send_client_message(
-            &ClientMessage::JoinGame { 
-                name: menu_state.name.clone(),
-                plane: menu_state.plane.clone(),
-                color: menu_state.color.clone()
-            },
-            &mut reader.stream
-        );
+        if menu_state.watch_replay {
+            let path = menu_state.selected_replay().unwrap().clone();
+            if run_replay(&path, &mut canvas, &mut assets, &mut event_pump, Some(&mut reader))?
+                == StateResult::Disconnected
+            {
+                break 'mainloop;
+            }
+            continue 'mainloop;
+        }
 
-        let main_state = &mut MainState::new(my_id);
+        if menu_state.spectate {
+            send_client_message(&ClientMessage::Spectate, &mut reader.stream);
+        } else {
+            send_client_message(
+                &ClientMessage::JoinGame { 
+                    name: menu_state.name.clone(),
+                    plane: menu_state.plane.clone(),
+                    color: menu_state.color.clone()
+                },
+                &mut reader.stream
+            );
+        }
+
+        let main_state = &mut MainState::new(my_id, menu_state.plane, menu_state.spectate);
         'gameloop: loop {
             for event in event_pump.poll_iter() {
                 match event {
                     Event::Quit{..} => break 'mainloop,
+                    Event::KeyDown {keycode: Some(kc), repeat: false, ..} => {
+                        let result = main_state.key_down_event(kc, &mut reader.stream);
+                        if result == StateResult::GotoNext {
+                            break 'gameloop;
+                        }
+                    }
+                    Event::KeyDown {keycode: Some(Keycode::Backspace), repeat: true, ..} => {
+                        main_state.chat.key_down_event(Keycode::Backspace);
+                    }
+                    Event::TextInput {text, ..} => main_state.chat.text_input(&text),
                     _ => {}
                 }
             }
+            // Text is only typed into the chat
+            if main_state.chat.is_typing() != video_subsystem.text_input().is_active() {
+                if main_state.chat.is_typing() {
+                    video_subsystem.text_input().start();
+                } else {
+                    video_subsystem.text_input().stop();
+                }
+            }
             rendering::setup_coordinates(&mut canvas)?;
 
             canvas.set_draw_color(sdl2::pixels::Color::RGB(25, 25, 25));
@@ -344,8 +660,10 @@ pub fn main() -> Result<(), String> {
 
             canvas.present();
 
-            if state_result == StateResult::GotoNext {
-                break 'gameloop;
+            match state_result {
+                StateResult::Continue => {}
+                StateResult::GotoNext => break 'gameloop,
+                StateResult::Disconnected => break 'mainloop,
             }
         }
     }
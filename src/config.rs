@@ -0,0 +1,126 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde_derive::Deserialize;
+
+use libplen::battle_royale::BattleRoyaleConfig;
+use libplen::bot::BotConfig;
+use libplen::ctf::CtfConfig;
+use libplen::gamemode::ModeName;
+use libplen::koth::KothConfig;
+use libplen::log::LogConfig;
+use libplen::warn;
+use libplen::race::RaceConfig;
+use libplen::round::RoundConfig;
+use libplen::teams::TeamConfig;
+
+use crate::metrics::MetricsConfig;
+use crate::recording::RecordingConfig;
+use crate::votes::VoteConfig;
+
+/**
+ * Server settings, read from a toml file. Every field has a default so an
+ * empty or missing file gives a working server.
+ */
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct ServerConfig {
+    pub address: String,
+    // Relative paths are resolved against the directory of the config file
+    pub ban_file: PathBuf,
+    pub moderation_log: PathBuf,
+    pub stats_file: PathBuf,
+    pub mode: ModeName,
+    pub round: RoundConfig,
+    pub teams: TeamConfig,
+    pub ctf: CtfConfig,
+    pub koth: KothConfig,
+    pub battle_royale: BattleRoyaleConfig,
+    pub race: RaceConfig,
+    pub bots: BotConfig,
+    pub recording: RecordingConfig,
+    pub metrics: MetricsConfig,
+    pub log: LogConfig,
+    pub votes: VoteConfig,
+    // Players that /login with this can use the admin chat commands, which
+    // are off without a password
+    pub admin_password: Option<String>,
+    // Seed of the simulation, a random one is picked if it isn't set
+    pub seed: Option<u64>,
+    // Fixed spawn points, random points are picked if this is empty
+    pub spawn_points: Vec<(f32, f32)>,
+    // The content of the config file, empty when using the defaults
+    #[serde(skip)]
+    pub source: String,
+    #[serde(skip)]
+    directory: PathBuf,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            address: String::from("0.0.0.0:4444"),
+            ban_file: PathBuf::from("bans.toml"),
+            moderation_log: PathBuf::from("moderation.log"),
+            stats_file: PathBuf::from("stats.json"),
+            mode: ModeName::default(),
+            round: RoundConfig::default(),
+            teams: TeamConfig::default(),
+            ctf: CtfConfig::default(),
+            koth: KothConfig::default(),
+            battle_royale: BattleRoyaleConfig::default(),
+            race: RaceConfig::default(),
+            bots: BotConfig::default(),
+            recording: RecordingConfig::default(),
+            metrics: MetricsConfig::default(),
+            log: LogConfig::default(),
+            votes: VoteConfig::default(),
+            admin_password: None,
+            seed: None,
+            spawn_points: vec!(),
+            source: String::new(),
+            directory: PathBuf::from("."),
+        }
+    }
+}
+
+impl ServerConfig {
+    /**
+     * Loads the config from the path in the PLEN_CONFIG environment variable,
+     * or server.toml in the current directory.
+     */
+    pub fn load() -> io::Result<Self> {
+        let path = std::env::var("PLEN_CONFIG")
+            .unwrap_or(String::from("server.toml"));
+        Self::load_from(Path::new(&path))
+    }
+
+    pub fn load_from(path: &Path) -> io::Result<Self> {
+        let mut config: ServerConfig = match fs::read_to_string(path) {
+            Ok(content) => {
+                let mut config: ServerConfig = toml::from_str(&content)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                config.source = content;
+                config
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+                warn!("No config found at {}, using defaults", path.display());
+                ServerConfig::default()
+            }
+            Err(e) => return Err(e),
+        };
+
+        config.directory = path.parent()
+            .map(|p| p.to_path_buf())
+            .unwrap_or(PathBuf::from("."));
+        Ok(config)
+    }
+
+    /**
+     * Resolves a path from the config relative to the config file
+     */
+    pub fn resolve(&self, path: &Path) -> PathBuf {
+        self.directory.join(path)
+    }
+}
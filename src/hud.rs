@@ -0,0 +1,538 @@
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+
+use libplen::battle_royale::ZoneState;
+use libplen::gamemode::GameModeKind;
+use libplen::gamestate::GameState;
+use libplen::koth::Holder;
+use libplen::math::{Vec2, vec2};
+use libplen::messages::VoteStatus;
+use libplen::player::{DeadPlayer, PlaneType};
+use libplen::round::{Round, RoundPhase};
+use libplen::stats::LifetimeStats;
+use libplen::teams::TEAMS;
+
+use crate::assets::Assets;
+use crate::map;
+use crate::menu;
+use crate::rendering;
+
+pub fn draw_text(
+    canvas: &mut Canvas<Window>,
+    assets: &Assets,
+    text: &str,
+    color: (u8, u8, u8, u8),
+    pos: Vec2,
+) -> Result<(), String> {
+    let surface = assets.font.render(text)
+        .blended(color)
+        .expect("Could not render text");
+    let texture_creator = canvas.texture_creator();
+    let texture = texture_creator.create_texture_from_surface(surface).unwrap();
+    rendering::draw_texture_centered(canvas, &texture, pos)
+}
+
+pub fn format_time(seconds: f32) -> String {
+    let seconds = seconds.max(0.).ceil() as u32;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+fn format_lap_time(seconds: f32) -> String {
+    let seconds = seconds.max(0.);
+    format!("{}:{:05.2}", (seconds / 60.) as u32, seconds % 60.)
+}
+
+pub fn draw_round_status(
+    canvas: &mut Canvas<Window>,
+    assets: &Assets,
+    round: &Round,
+) -> Result<(), String> {
+    let text = match round.phase {
+        RoundPhase::Warmup => {
+            format!("Warmup, round {} starts in {}", round.number, format_time(round.time_left))
+        }
+        RoundPhase::InProgress if round.time_left.is_finite() => {
+            format!("Round {}  {}", round.number, format_time(round.time_left))
+        }
+        RoundPhase::InProgress => format!("Round {}", round.number),
+        RoundPhase::Ended => format!("Round {} is over!", round.number),
+        RoundPhase::Intermission => {
+            format!("Next round in {}", format_time(round.time_left))
+        }
+    };
+
+    let x = canvas.logical_size().0 as f32 * 0.5;
+    draw_text(canvas, assets, &text, (255, 255, 255, 255), vec2(x, 15.))?;
+
+    match round.phase {
+        RoundPhase::Ended | RoundPhase::Intermission => draw_round_results(canvas, assets, round),
+        _ => Ok(())
+    }
+}
+
+fn draw_round_results(
+    canvas: &mut Canvas<Window>,
+    assets: &Assets,
+    round: &Round,
+) -> Result<(), String> {
+    const ROW_HEIGHT: f32 = 25.;
+    const MAX_ROWS: usize = 10;
+
+    let (screen_w, screen_h) = canvas.logical_size();
+    let rows = round.results.len().min(MAX_ROWS);
+    let height = ROW_HEIGHT * (rows + 2) as f32;
+    let top = screen_h as f32 * 0.3;
+
+    canvas.set_draw_color((0, 0, 0, 180));
+    canvas.fill_rect(sdl2::rect::Rect::new(
+        (screen_w as f32 * 0.25) as i32,
+        top as i32,
+        (screen_w as f32 * 0.5) as u32,
+        height as u32,
+    ))?;
+
+    let center = screen_w as f32 * 0.5;
+    draw_text(
+        canvas,
+        assets,
+        &format!("Results of round {}", round.number),
+        (255, 255, 100, 255),
+        vec2(center, top + ROW_HEIGHT * 0.5)
+    )?;
+
+    for (i, result) in round.results.iter().take(MAX_ROWS).enumerate() {
+        let y = top + ROW_HEIGHT * (i as f32 + 1.5);
+        draw_text(
+            canvas,
+            assets,
+            &format!("{}. {}  {}", i + 1, result.name, result.score),
+            (255, 255, 255, 255),
+            vec2(center, y)
+        )?;
+    }
+    Ok(())
+}
+
+/**
+ * Draws the score of each team below the round status in team games
+ */
+pub fn draw_team_scores(
+    canvas: &mut Canvas<Window>,
+    assets: &Assets,
+    game_state: &GameState,
+) -> Result<(), String> {
+    if !game_state.teams.enabled {
+        return Ok(());
+    }
+
+    let x = canvas.logical_size().0 as f32 * 0.5;
+    for (i, team) in TEAMS.iter().enumerate() {
+        let score = game_state.scoreboard.team_scores.get(team).cloned().unwrap_or(0);
+        let offset = if i == 0 {-60.} else {60.};
+        draw_text(
+            canvas, assets, &format!("{} {}", team.name(), score),
+            team.color().rgba(), vec2(x + offset, 40.)
+        )?;
+    }
+    Ok(())
+}
+
+/**
+ * Draws a bar for every control zone at the bottom of the screen showing
+ * who holds it
+ */
+pub fn draw_control_bar(
+    canvas: &mut Canvas<Window>,
+    assets: &Assets,
+    game_state: &GameState,
+) -> Result<(), String> {
+    const BAR_WIDTH: f32 = 220.;
+    const BAR_HEIGHT: f32 = 24.;
+    const SPACING: f32 = 10.;
+
+    let koth = match &game_state.mode {
+        GameModeKind::KingOfTheHill(koth) => koth,
+        _ => return Ok(()),
+    };
+
+    let (screen_w, screen_h) = canvas.logical_size();
+    let total_width = koth.zones.len() as f32 * (BAR_WIDTH + SPACING) - SPACING;
+    let left = (screen_w as f32 - total_width) * 0.5;
+    let y = screen_h as f32 - BAR_HEIGHT - 15.;
+
+    for (i, zone) in koth.zones.iter().enumerate() {
+        let x = left + i as f32 * (BAR_WIDTH + SPACING);
+        let (r, g, b, _) = map::zone_color(zone, game_state);
+        canvas.set_draw_color((r, g, b, 120));
+        canvas.fill_rect(sdl2::rect::Rect::new(
+            x as i32, y as i32, BAR_WIDTH as u32, BAR_HEIGHT as u32
+        ))?;
+
+        let holder = if zone.contested {
+            String::from("contested")
+        } else {
+            match zone.holder {
+                Some(Holder::Team(team)) => format!("{} team", team.name()),
+                Some(Holder::Player(id)) => game_state.get_player_by_id(id)
+                    .map(|p| p.name.clone())
+                    .unwrap_or(String::from("neutral")),
+                None => String::from("neutral"),
+            }
+        };
+        draw_text(
+            canvas, assets, &format!("Zone {}: {}", i + 1, holder), (255, 255, 255, 255),
+            vec2(x + BAR_WIDTH * 0.5, y + BAR_HEIGHT * 0.5)
+        )?;
+    }
+    Ok(())
+}
+
+/**
+ * Draws the state of the safe zone and the number of planes left during a
+ * battle royale match, and the winner when it is over
+ */
+pub fn draw_battle_royale(
+    canvas: &mut Canvas<Window>,
+    assets: &Assets,
+    game_state: &GameState,
+) -> Result<(), String> {
+    let battle_royale = match &game_state.mode {
+        GameModeKind::BattleRoyale(battle_royale) => battle_royale,
+        _ => return Ok(()),
+    };
+    let (screen_w, screen_h) = canvas.logical_size();
+    let x = screen_w as f32 * 0.5;
+
+    if battle_royale.active {
+        let zone = &battle_royale.zone;
+        let status = match zone.state {
+            ZoneState::Waiting => format!("Zone shrinks in {}", format_time(zone.time_left)),
+            ZoneState::Shrinking => String::from("Zone is shrinking!"),
+            ZoneState::Closed => String::from("Zone is closed"),
+        };
+        let alive = game_state.players.iter().filter(|p| p.health > 0).count();
+        let text = format!("{}   {} planes left", status, alive);
+        draw_text(canvas, assets, &text, (255, 140, 100, 255), vec2(x, 40.))?;
+    }
+
+    match (game_state.round.phase, game_state.round.results.first()) {
+        (RoundPhase::Ended, Some(winner)) | (RoundPhase::Intermission, Some(winner)) => {
+            draw_text(
+                canvas, assets, &format!("{} is the last plane standing!", winner.name),
+                (255, 255, 100, 255), vec2(x, screen_h as f32 * 0.2)
+            )
+        }
+        _ => Ok(())
+    }
+}
+
+/**
+ * Draws the lap count and timing of this player during a race, and the
+ * record of the plane they fly
+ */
+pub fn draw_race(
+    canvas: &mut Canvas<Window>,
+    assets: &Assets,
+    game_state: &GameState,
+    my_id: u64,
+) -> Result<(), String> {
+    const ROW_HEIGHT: f32 = 22.;
+
+    let race = match &game_state.mode {
+        GameModeKind::Race(race) => race,
+        _ => return Ok(()),
+    };
+    let racer = race.racers.get(&my_id).cloned().unwrap_or_default();
+    let plane = game_state.get_player_by_id(my_id).map(|p| p.planetype);
+    let (_, screen_h) = canvas.logical_size();
+    let x = 120.;
+    let top = screen_h as f32 * 0.35;
+
+    let laps = match race.laps {
+        0 => format!("Lap {}", racer.laps + 1),
+        laps => format!("Lap {}/{}", (racer.laps + 1).min(laps), laps),
+    };
+    let current = match racer.lap_start {
+        Some(_) => racer.splits.last().map(|t| format_lap_time(*t))
+            .unwrap_or(String::from("-")),
+        None => String::from("Fly through the start"),
+    };
+    let mut rows = vec!(
+        (laps, (255, 255, 255, 255)),
+        (format!("Split {}", current), (255, 255, 255, 255)),
+        (format!("Last {}", racer.last_lap.map(format_lap_time).unwrap_or(String::from("-"))),
+            (255, 255, 255, 255)),
+        (format!("Best {}", racer.best_lap.map(format_lap_time).unwrap_or(String::from("-"))),
+            (255, 255, 255, 255)),
+    );
+    if let Some(delta) = racer.split_delta {
+        let color = if delta <= 0. {(100, 255, 100, 255)} else {(255, 100, 100, 255)};
+        rows.push((format!("{:+.2}", delta), color));
+    }
+    if let Some(record) = plane.and_then(|plane| race.records.get(&plane)) {
+        rows.push((
+            format!("Record {} by {}", format_lap_time(record.time), record.name),
+            (255, 255, 100, 255)
+        ));
+    }
+
+    for (i, (text, color)) in rows.iter().enumerate() {
+        draw_text(canvas, assets, text, *color, vec2(x, top + ROW_HEIGHT * i as f32))?;
+    }
+    Ok(())
+}
+
+/**
+ * Draws the scoreboard overlay, shown while tab is held
+ */
+pub fn draw_scoreboard(
+    canvas: &mut Canvas<Window>,
+    assets: &Assets,
+    game_state: &GameState,
+    my_id: u64,
+) -> Result<(), String> {
+    const ROW_HEIGHT: f32 = 22.;
+    const COLUMNS: [(&str, f32); 7] = [
+        ("Name", 0.2),
+        ("Score", 0.42),
+        ("K", 0.52),
+        ("D", 0.59),
+        ("A", 0.66),
+        ("Damage", 0.76),
+        ("Accuracy", 0.88),
+    ];
+
+    let (screen_w, screen_h) = canvas.logical_size();
+    let (screen_w, screen_h) = (screen_w as f32, screen_h as f32);
+    let mut entries = game_state.scoreboard.sorted();
+    // Group the players by team, the sort is stable so each team stays sorted
+    entries.sort_by_key(|(_, stats)| stats.team);
+    let top = screen_h * 0.15;
+    let left = screen_w * 0.05;
+    let width = screen_w * 0.9;
+
+    canvas.set_draw_color((0, 0, 0, 200));
+    canvas.fill_rect(sdl2::rect::Rect::new(
+        left as i32,
+        top as i32,
+        width as u32,
+        (ROW_HEIGHT * (entries.len() + 1) as f32 + 10.) as u32,
+    ))?;
+
+    for (title, x) in COLUMNS.iter() {
+        draw_text(
+            canvas, assets, title, (255, 255, 100, 255),
+            vec2(left + width * x, top + ROW_HEIGHT * 0.5 + 5.)
+        )?;
+    }
+
+    for (i, (id, stats)) in entries.iter().enumerate() {
+        let y = top + ROW_HEIGHT * (i as f32 + 1.5) + 5.;
+        if *id == my_id {
+            canvas.set_draw_color((255, 255, 255, 40));
+            canvas.fill_rect(sdl2::rect::Rect::new(
+                left as i32,
+                (y - ROW_HEIGHT * 0.5) as i32,
+                width as u32,
+                ROW_HEIGHT as u32,
+            ))?;
+        }
+
+        let name_color = game_state.get_player_by_id(*id)
+            .map(|p| p.display_color().rgba())
+            .unwrap_or((160, 160, 160, 255));
+        let cells = [
+            stats.score().to_string(),
+            stats.kills.to_string(),
+            stats.deaths.to_string(),
+            stats.assists.to_string(),
+            stats.damage_dealt.to_string(),
+            format!("{:.0}%", stats.accuracy() * 100.),
+        ];
+
+        draw_text(canvas, assets, &stats.name, name_color, vec2(left + width * COLUMNS[0].1, y))?;
+        for (cell, (_, x)) in cells.iter().zip(COLUMNS.iter().skip(1)) {
+            draw_text(canvas, assets, cell, (255, 255, 255, 255), vec2(left + width * x, y))?;
+        }
+    }
+    Ok(())
+}
+
+/**
+ * Draws the lifetime stats screen with the top list and the stats of this
+ * player, toggled with L
+ */
+pub fn draw_lifetime_stats(
+    canvas: &mut Canvas<Window>,
+    assets: &Assets,
+    top: &[(String, LifetimeStats)],
+    mine: Option<&LifetimeStats>,
+) -> Result<(), String> {
+    const ROW_HEIGHT: f32 = 22.;
+    const COLUMNS: [(&str, f32); 6] = [
+        ("Name", 0.15),
+        ("Kills", 0.35),
+        ("Deaths", 0.46),
+        ("K/D", 0.56),
+        ("Plane", 0.71),
+        ("Weapon", 0.88),
+    ];
+
+    let (screen_w, screen_h) = canvas.logical_size();
+    let (screen_w, screen_h) = (screen_w as f32, screen_h as f32);
+    let top_y = screen_h * 0.1;
+    let left = screen_w * 0.05;
+    let width = screen_w * 0.9;
+    let rows = top.len() + if mine.is_some() {3} else {1};
+
+    canvas.set_draw_color((0, 0, 0, 200));
+    canvas.fill_rect(sdl2::rect::Rect::new(
+        left as i32,
+        top_y as i32,
+        width as u32,
+        (ROW_HEIGHT * (rows + 1) as f32) as u32,
+    ))?;
+
+    draw_text(
+        canvas, assets, "All time best, press L to close", (255, 255, 100, 255),
+        vec2(screen_w * 0.5, top_y + ROW_HEIGHT * 0.5)
+    )?;
+    for (title, x) in COLUMNS.iter() {
+        draw_text(
+            canvas, assets, title, (255, 255, 100, 255),
+            vec2(left + width * x, top_y + ROW_HEIGHT * 1.5)
+        )?;
+    }
+
+    let rows = top.iter().map(|(name, stats)| (name.as_str(), stats))
+        .chain(mine.map(|stats| ("You", stats)));
+    for (i, (name, stats)) in rows.enumerate() {
+        // Leave an empty row before the stats of this player
+        let row = if i < top.len() {i} else {i + 1};
+        let y = top_y + ROW_HEIGHT * (row as f32 + 2.5);
+        let cells = [
+            name.to_string(),
+            stats.kills.to_string(),
+            stats.deaths.to_string(),
+            format!("{:.2}", stats.kill_death_ratio()),
+            stats.favourite_plane().map(|p| p.name().to_string()).unwrap_or(String::from("-")),
+            stats.favourite_weapon().map(|w| w.name().to_string()).unwrap_or(String::from("-")),
+        ];
+        for (cell, (_, x)) in cells.iter().zip(COLUMNS.iter()) {
+            draw_text(canvas, assets, cell, (255, 255, 255, 255), vec2(left + width * x, y))?;
+        }
+    }
+    Ok(())
+}
+
+/**
+ * What the camera of a spectator is on, and how to move it. The powerups of
+ * the followed player are drawn by the map like our own.
+ */
+pub fn draw_spectator(
+    canvas: &mut Canvas<Window>,
+    assets: &Assets,
+    game_state: &GameState,
+    following: Option<u64>,
+    controls: &str,
+) -> Result<(), String> {
+    let (screen_w, screen_h) = canvas.logical_size();
+    let x = screen_w as f32 * 0.5;
+    let y = screen_h as f32 - 140.;
+
+    match following.and_then(|id| game_state.get_player_by_id(id)) {
+        Some(player) => {
+            let text = format!("Following {} ({})", player.name, player.planetype.name());
+            draw_text(canvas, assets, &text, player.display_color().rgba(), vec2(x, y))?;
+
+            const HEALTH_BAR_WIDTH: f32 = 200.;
+            let max_health = player.planetype.health() as f32;
+            let fraction = (player.health as f32 / max_health).clamp(0., 1.);
+            let left = (x - HEALTH_BAR_WIDTH / 2.) as i32;
+            canvas.set_draw_color((255, 0, 0, 255));
+            canvas.fill_rect(sdl2::rect::Rect::new(left, y as i32 + 15, HEALTH_BAR_WIDTH as u32, 12))?;
+            canvas.set_draw_color((0, 255, 0, 255));
+            canvas.fill_rect(sdl2::rect::Rect::new(
+                left, y as i32 + 15, (HEALTH_BAR_WIDTH * fraction) as u32, 12
+            ))?;
+            draw_text(
+                canvas, assets, &format!("{} / {}", player.health.max(0), max_health),
+                (255, 255, 255, 255), vec2(x, y + 21.)
+            )?;
+        }
+        None => {
+            draw_text(canvas, assets, "Spectating", (255, 255, 255, 255), vec2(x, y))?;
+        }
+    }
+
+    draw_text(canvas, assets, controls, (200, 200, 200, 255), vec2(x, y + 45.))
+}
+
+pub fn draw_respawn_prompt(
+    canvas: &mut Canvas<Window>,
+    assets: &Assets,
+    dead_player: Option<&DeadPlayer>,
+    respawn_plane: PlaneType,
+    waiting_for_round: bool,
+) -> Result<(), String> {
+    let (screen_w, screen_h) = canvas.logical_size();
+    let x = screen_w as f32 * 0.5;
+    let y = screen_h as f32 * 0.6;
+
+    let countdown = match dead_player {
+        _ if waiting_for_round => String::from("Waiting for the next round"),
+        Some(dead) if !dead.can_respawn() => {
+            format!("Respawning possible in {:.0}", dead.respawn_time.ceil())
+        }
+        _ => String::from("Press Enter to respawn"),
+    };
+    draw_text(canvas, assets, &countdown, (255, 255, 255, 255), vec2(x, y))?;
+
+    let planes = menu::PLANES.iter().enumerate()
+        .map(|(i, plane)| {
+            let marker = if *plane == respawn_plane {"*"} else {""};
+            format!("{}{} {}{}", marker, i + 1, plane.name(), marker)
+        })
+        .collect::<Vec<_>>()
+        .join("   ");
+    draw_text(canvas, assets, &planes, (255, 255, 100, 255), vec2(x, y + 25.))?;
+    draw_text(
+        canvas, assets, "Press 1-4 to pick a plane, Escape for the menu",
+        (200, 200, 200, 255), vec2(x, y + 50.)
+    )
+}
+
+/**
+ * Draws the running vote below the round status, with the keys to vote
+ * until the player has voted
+ */
+pub fn draw_vote(
+    canvas: &mut Canvas<Window>,
+    assets: &Assets,
+    status: &VoteStatus,
+    since_received: f32,
+) -> Result<(), String> {
+    const WIDTH: f32 = 420.;
+    const HEIGHT: f32 = 80.;
+
+    let x = canvas.logical_size().0 as f32 * 0.5;
+    let y = 70.;
+    canvas.set_draw_color((0, 0, 0, 150));
+    canvas.fill_rect(sdl2::rect::Rect::new(
+        (x - WIDTH * 0.5) as i32, y as i32, WIDTH as u32, HEIGHT as u32
+    ))?;
+
+    let title = format!("{} wants to: {}", status.initiator, status.description);
+    draw_text(canvas, assets, &title, (255, 255, 255, 255), vec2(x, y + 15.))?;
+    let tally = format!(
+        "Yes {}   No {}   {} needed   {}",
+        status.yes, status.no, status.needed, format_time(status.time_left - since_received)
+    );
+    draw_text(canvas, assets, &tally, (200, 200, 200, 255), vec2(x, y + 40.))?;
+    let prompt = match status.voted {
+        Some(true) => "You voted yes, F2 to change",
+        Some(false) => "You voted no, F1 to change",
+        None => "F1: yes   F2: no",
+    };
+    draw_text(canvas, assets, prompt, (255, 255, 100, 255), vec2(x, y + 65.))
+}
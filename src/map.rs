@@ -8,8 +8,11 @@ use sdl2::video::Window;
 use libplen::player;
 use libplen::powerups::PowerUpKind;
 use libplen::constants;
+use libplen::gamemode::GameModeKind;
 use libplen::gamestate::GameState;
 use libplen::projectiles::{ProjectileKind, Projectile};
+use libplen::koth::{ControlZone, Holder};
+use libplen::teams::Team;
 use libplen::math::{self, Vec2, vec2};
 
 use crate::assets::Assets;
@@ -94,6 +97,22 @@ pub struct RadarObject {
     position: Vec2,
 }
 
+/**
+ * Contested zones are yellow, held zones have the color of their holder
+ */
+pub fn zone_color(zone: &ControlZone, game_state: &GameState) -> (u8, u8, u8, u8) {
+    if zone.contested {
+        return (255, 255, 100, 255);
+    }
+    match zone.holder {
+        Some(Holder::Team(team)) => team.color().rgba(),
+        Some(Holder::Player(id)) => game_state.get_player_by_id(id)
+            .map(|p| p.display_color().rgba())
+            .unwrap_or((255, 255, 255, 255)),
+        None => (255, 255, 255, 255),
+    }
+}
+
 pub struct Map {
     smoke_particles: ParticleSystem<SmokeParticle>,
     explosion_particles: ParticleSystem<ExplosionParticle>,
@@ -103,6 +122,8 @@ pub struct Map {
     spark_timer: f32,
     start_time: Instant,
     radar_angle: f32,
+    // Spectators can't affect the game, so they see invisible planes too
+    pub full_info: bool,
 }
 
 impl Map {
@@ -116,6 +137,7 @@ impl Map {
             spark_timer: 0.,
             start_time: Instant::now(),
             radar_angle: 0.,
+            full_info: false,
         }
     }
 
@@ -248,6 +270,7 @@ impl Map {
         };
 
         let my_pos = my_player.position;
+        let full_info = self.full_info;
 
         for tile_x in &[-1., 0., 1.] {
             for tile_y in &[-1., 0., 1.] {
@@ -258,7 +281,7 @@ impl Map {
                 let scale = constants::MINI_MAP_SIZE / constants::WORLD_SIZE;
                 self.radar_objects.add_particles(
                     game_state.players.iter().filter_map(|player| {
-                        if player.id == my_player.id || player.is_invisible() {
+                        if player.id == my_player.id || (player.is_invisible() && !full_info) {
                             // don't draw player if invisible
                             // and my player is always drawn
                             return None;
@@ -268,7 +291,7 @@ impl Map {
                             return Some(RadarObject {
                                 object_type: RadarObjectType::Plane {
                                     rotation: player.rotation,
-                                    color: player.color.rgb(),
+                                    color: player.display_color().rgb(),
                                 },
                                 position: position,
                                 lifetime: constants::RADAR_FADEOUT_TIME,
@@ -369,7 +392,7 @@ impl Map {
                 )?;
 
                 for player in &game_state.players {
-                    if player.is_invisible() && my_id != player.id {
+                    if player.is_invisible() && my_id != player.id && !self.full_info {
                         // don't draw player if invisible
                         continue;
                     }
@@ -405,10 +428,10 @@ impl Map {
         Self::draw_red_hit_effect(hit_effect_timer, canvas);
 
         if let Some(my_player) = game_state.get_player_by_id(my_id) {
-            self.draw_mini_map(canvas, assets, my_player)?;
+            self.draw_mini_map(canvas, assets, game_state, my_player)?;
         }
 
-        Self::draw_ui(my_id, game_state, canvas, assets, powerup_rotation)?;
+        Self::draw_ui(my_id, game_state, canvas, assets, powerup_rotation, self.full_info)?;
         Self::draw_killfeed(canvas, assets, game_state)?;
         Self::draw_debug_lines(canvas, &game_state.debug_lines, camera_position, screen_center)?;
 
@@ -488,16 +511,95 @@ impl Map {
             rendering::draw_texture_centered(canvas, &assets.spark, position)?;
         }
 
+        let battle_royale = match &game_state.mode {
+            GameModeKind::BattleRoyale(battle_royale) if battle_royale.active => Some(battle_royale),
+            _ => None,
+        };
+        if let Some(battle_royale) = battle_royale {
+            let zone = &battle_royale.zone;
+            rendering::draw_circle(
+                canvas,
+                world_to_screen_position(zone.target_center),
+                zone.target_radius,
+                (255, 255, 255, 100)
+            )?;
+            let center = world_to_screen_position(zone.center);
+            for i in 0..3 {
+                rendering::draw_circle(canvas, center, zone.radius + i as f32 * 3., (255, 60, 60, 255))?;
+            }
+        }
+
+        if let GameModeKind::Race(race) = &game_state.mode {
+            let next = race.racers.get(&my_id).map(|racer| racer.next_checkpoint);
+            for (i, checkpoint) in race.checkpoints.iter().enumerate() {
+                let center = world_to_screen_position(*checkpoint);
+                if Some(i) == next {
+                    for offset in 0..3 {
+                        rendering::draw_circle(
+                            canvas, center, race.checkpoint_radius + offset as f32 * 3., (255, 255, 100, 255)
+                        )?;
+                    }
+                } else {
+                    rendering::draw_circle(canvas, center, race.checkpoint_radius, (255, 255, 255, 90))?;
+                }
+                let label = if i == 0 {String::from("Start")} else {i.to_string()};
+                let text = assets.font.render(&label)
+                    .blended((255, 255, 255, 180))
+                    .expect("Could not render text");
+                let texture_creator = canvas.texture_creator();
+                let text_texture = texture_creator.create_texture_from_surface(text).unwrap();
+                rendering::draw_texture_centered(canvas, &text_texture, center)?;
+            }
+
+            // The ghost of the best lap in our plane
+            let ghost = race.racers.get(&my_id).and_then(|racer| racer.ghost);
+            let my_plane = game_state.get_player_by_id(my_id).map(|p| p.planetype);
+            if let (Some((position, rotation)), Some(plane)) = (ghost, my_plane) {
+                let texture = &mut assets.planes[plane];
+                texture.set_alpha_mod(70);
+                rendering::draw_texture_rotated(
+                    canvas, texture, world_to_screen_position(position), rotation
+                )?;
+                texture.set_alpha_mod(255);
+            }
+        }
+
+        if let GameModeKind::KingOfTheHill(koth) = &game_state.mode {
+            for zone in &koth.zones {
+                let center = world_to_screen_position(zone.position);
+                let color = zone_color(zone, game_state);
+                rendering::draw_circle(canvas, center, zone.radius, color)?;
+                rendering::draw_circle(canvas, center, zone.radius - 4., color)?;
+            }
+        }
+
+        if let GameModeKind::CaptureTheFlag(ctf) = &game_state.mode {
+            for flag in &ctf.flags {
+                let (r, g, b, _) = flag.team.color().rgba();
+                rendering::draw_circle(
+                    canvas,
+                    world_to_screen_position(flag.base),
+                    constants::BASE_RADIUS,
+                    (r, g, b, 150)
+                )?;
+                Self::draw_flag(canvas, world_to_screen_position(flag.position), flag.team)?;
+            }
+        }
+
         for player in &game_state.players {
-            if player.is_invisible() && player.id != my_id {
+            if player.is_invisible() && player.id != my_id && !self.full_info {
                 // don't draw player if invisible
                 continue;
             }
-            let opacity = if player.is_invisible() {128} else {255};
+            let blink_hidden = player.is_spawn_protected() &&
+                (player.spawn_protection * constants::SPAWN_PROTECTION_BLINK_SPEED) as i32 % 2 == 0;
+            let opacity = if player.is_invisible() || blink_hidden {128} else {255};
             let position = world_to_screen_position(player.position);
             let texture = &mut assets.planes[player.planetype];
 
+            let (r, g, b) = player.team.map(|team| team.tint()).unwrap_or((255, 255, 255));
             texture.set_alpha_mod(opacity);
+            texture.set_color_mod(r, g, b);
             rendering::draw_texture_rotated_and_scaled(
                 canvas,
                 &texture,
@@ -505,6 +607,7 @@ impl Map {
                 player.rotation,
                 vec2(1.0 - player.angular_velocity.abs() / 8., 1.0)
             )?;
+            texture.set_color_mod(255, 255, 255);
 
             if player.has_powerup(PowerUpKind::Afterburner) {
                 let fire_size = 32;
@@ -536,7 +639,7 @@ impl Map {
             }
 
             let nametag = assets.font.render(&player.name)
-                .blended(player.color.rgba())
+                .blended(player.display_color().rgba())
                 .expect("Could not render text");
 
             let texture_creator = canvas.texture_creator();
@@ -637,6 +740,7 @@ impl Map {
         canvas: &mut Canvas<Window>,
         assets: &Assets,
         powerup_rotation: f32,
+        spectating: bool,
     ) -> Result<(), String> {
         let mut x_pos = 40.;
         let y_pos = canvas.logical_size().1 as f32 - 20. - constants::POWERUP_RADIUS as f32;
@@ -664,6 +768,9 @@ impl Map {
                     vec2(scale, scale)
                 )?;
 
+                if spectating {
+                    return Ok(());
+                }
                 let instruction = assets.font.render("Press E to activate")
                     .blended((255, 255, 255, 255))
                     .expect("Could not render text");
@@ -708,10 +815,20 @@ impl Map {
         Ok(())
     }
 
+    fn draw_flag(canvas: &mut Canvas<Window>, position: Vec2, team: Team) -> Result<(), String> {
+        const POLE_HEIGHT: i32 = 40;
+        let (x, y) = (position.x as i32, position.y as i32);
+        canvas.set_draw_color((220, 220, 220));
+        canvas.draw_line((x, y), (x, y - POLE_HEIGHT))?;
+        canvas.set_draw_color(team.color().rgba());
+        canvas.fill_rect(sdl2::rect::Rect::new(x + 1, y - POLE_HEIGHT, 24, 14))
+    }
+
     fn draw_mini_map(
         &self,
         canvas: &mut Canvas<Window>,
         assets: &mut Assets,
+        game_state: &GameState,
         my_player: &player::Player,
     ) -> Result<(), String> {
         let (screen_w, screen_h) = canvas.logical_size();
@@ -758,6 +875,51 @@ impl Map {
             }
         }
 
+        let scale = constants::MINI_MAP_SIZE / constants::WORLD_SIZE;
+        if let GameModeKind::KingOfTheHill(koth) = &game_state.mode {
+            for zone in &koth.zones {
+                let pos = mini_map_center +
+                    math::wrapped_difference(my_player.position, zone.position) * scale;
+                rendering::draw_circle(canvas, pos, zone.radius * scale, zone_color(zone, game_state))?;
+            }
+        }
+
+        let battle_royale = match &game_state.mode {
+            GameModeKind::BattleRoyale(battle_royale) if battle_royale.active => Some(battle_royale),
+            _ => None,
+        };
+        if let Some(battle_royale) = battle_royale {
+            let zone = &battle_royale.zone;
+            let center = mini_map_center +
+                math::wrapped_difference(my_player.position, zone.center) * scale;
+            rendering::draw_circle(canvas, center, zone.radius * scale, (255, 60, 60, 255))?;
+            let target = mini_map_center +
+                math::wrapped_difference(my_player.position, zone.target_center) * scale;
+            rendering::draw_circle(canvas, target, zone.target_radius * scale, (255, 255, 255, 120))?;
+        }
+
+        let next_checkpoint = match &game_state.mode {
+            GameModeKind::Race(race) => race.racers.get(&my_player.id)
+                .and_then(|racer| race.checkpoints.get(racer.next_checkpoint)),
+            _ => None,
+        };
+        if let Some(checkpoint) = next_checkpoint {
+            let pos = mini_map_center +
+                math::wrapped_difference(my_player.position, *checkpoint) * scale;
+            canvas.set_draw_color((255, 255, 100, 255));
+            canvas.fill_rect(sdl2::rect::Rect::new(pos.x as i32 - 3, pos.y as i32 - 3, 6, 6))?;
+        }
+
+        // Flags are always shown, which also gives away their carriers
+        if let GameModeKind::CaptureTheFlag(ctf) = &game_state.mode {
+            for flag in &ctf.flags {
+                let pos = mini_map_center +
+                    math::wrapped_difference(my_player.position, flag.position) * scale;
+                canvas.set_draw_color(flag.team.color().rgba());
+                canvas.fill_rect(sdl2::rect::Rect::new(pos.x as i32 - 4, pos.y as i32 - 4, 8, 8))?;
+            }
+        }
+
         // Draw radar line
         let mini_map_edge = mini_map_center + vec2(
             self.radar_angle.cos(),
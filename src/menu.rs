@@ -1,13 +1,17 @@
+use std::path::PathBuf;
+
+use sdl2::keyboard::Keycode;
 use sdl2::render::Canvas;
 use sdl2::video::Window;
 
 use crate::assets::Assets;
 use crate::rendering;
+use crate::replay_player;
 use libplen::player;
 use libplen::constants;
 use libplen::math::vec2;
 
-const PLANES: [player::PlaneType; 4] = [
+pub const PLANES: [player::PlaneType; 4] = [
     player::PlaneType::SukaBlyat,
     player::PlaneType::HowdyCowboy,
     player::PlaneType::ElPolloRomero,
@@ -28,6 +32,11 @@ pub struct MenuState {
     pub color: player::Color,
     pub color_selection: usize,
     pub plane_selection: usize,
+    // Watch the game instead of flying
+    pub spectate: bool,
+    pub replays: Vec<PathBuf>,
+    pub replay_selection: usize,
+    pub watch_replay: bool,
 }
 
 impl MenuState {
@@ -38,6 +47,10 @@ impl MenuState {
             color: player::Color::Red,
             color_selection: 0,
             plane_selection: 0,
+            spectate: false,
+            replays: replay_player::list_replays(),
+            replay_selection: 0,
+            watch_replay: false,
         }
     }
 }
@@ -55,7 +68,32 @@ impl MenuState {
         let res_offset = rendering::calculate_resolution_offset(canvas);
         rendering::draw_texture(
             canvas, &text_texture, vec2(nx + 10., ny + 10.) + res_offset
-        )
+        )?;
+
+        let instruction = assets.font.render("Enter to fly, Tab to spectate")
+            .blended((200, 200, 200))
+            .expect("Could not render text");
+        let instruction_texture = texture_creator.create_texture_from_surface(instruction).unwrap();
+        rendering::draw_texture(
+            canvas, &instruction_texture, vec2(nx + 10., ny + 40.) + res_offset
+        )?;
+
+        if let Some(replay) = self.selected_replay() {
+            let name = replay.file_stem().map(|s| s.to_string_lossy()).unwrap_or_default();
+            let text = format!("F1 to watch replay {} (up/down to pick)", name);
+            let replay_text = assets.font.render(&text)
+                .blended((200, 200, 200))
+                .expect("Could not render text");
+            let replay_texture = texture_creator.create_texture_from_surface(replay_text).unwrap();
+            rendering::draw_texture(
+                canvas, &replay_texture, vec2(nx + 10., ny + 65.) + res_offset
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn selected_replay(&self) -> Option<&PathBuf> {
+        self.replays.get(self.replay_selection)
     }
 
     fn draw_selected_plane(&mut self, canvas: &mut Canvas<Window>, assets: &Assets) -> Result<(), String> {
@@ -151,6 +189,16 @@ impl MenuState {
         )
     }
 
+    pub fn key_down_event(&mut self, keycode: Keycode) {
+        match keycode {
+            Keycode::Up => self.replay_selection = self.replay_selection.saturating_sub(1),
+            Keycode::Down if self.replay_selection + 1 < self.replays.len() => {
+                self.replay_selection += 1;
+            }
+            _ => {}
+        }
+    }
+
     pub fn update(&mut self) {
         self.plane = PLANES[self.plane_selection].clone();
         self.color = COLORS[self.color_selection].clone();
@@ -0,0 +1,251 @@
+use std::collections::HashMap;
+use std::fmt::Write as FmtWrite;
+use std::io::{self, prelude::*};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde_derive::Deserialize;
+
+use libplen::gamestate::GameState;
+use libplen::scoreboard::{KillEvent, Weapon};
+use libplen::{info, warn};
+
+// Upper bounds of the tick duration histogram buckets, in seconds
+const TICK_BUCKETS: [f64; 10] = [0.0005, 0.001, 0.002, 0.005, 0.01, 0.02, 0.05, 0.1, 0.25, 1.];
+// How often the bytes per second are worked out
+const RATE_INTERVAL: Duration = Duration::from_secs(1);
+
+// Bytes are sent and received all over the server, so they are counted
+// outside of the metrics
+static BYTES_SENT: AtomicU64 = AtomicU64::new(0);
+static BYTES_RECEIVED: AtomicU64 = AtomicU64::new(0);
+
+pub fn count_sent(bytes: usize) {
+    BYTES_SENT.fetch_add(bytes as u64, Ordering::Relaxed);
+}
+
+pub fn count_received(bytes: usize) {
+    BYTES_RECEIVED.fetch_add(bytes as u64, Ordering::Relaxed);
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct MetricsConfig {
+    pub enabled: bool,
+    // Only reachable from the server itself unless this is changed
+    pub address: String,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            address: String::from("127.0.0.1:9184"),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Values {
+    // Ticks by the first bucket they fit in, the last one is for the slower
+    // ticks
+    tick_buckets: [u64; TICK_BUCKETS.len() + 1],
+    tick_seconds: f64,
+    clients: usize,
+    bots: usize,
+    sent_per_second: f64,
+    received_per_second: f64,
+    snapshot_bytes: u64,
+    projectiles: usize,
+    lasers: usize,
+    kills: HashMap<Weapon, u64>,
+    hurricane_active: bool,
+}
+
+impl Values {
+    /**
+     * Formats the values in the Prometheus text format
+     */
+    fn render(&self) -> String {
+        let mut out = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, samples: &[(String, String)]| {
+            let _ = writeln!(out, "# HELP plen_{} {}", name, help);
+            let _ = writeln!(out, "# TYPE plen_{} {}", name, kind);
+            for (suffix, value) in samples {
+                let _ = writeln!(out, "plen_{}{} {}", name, suffix, value);
+            }
+        };
+
+        let mut cumulative = 0;
+        let mut buckets = vec!();
+        for (bound, count) in TICK_BUCKETS.iter().zip(self.tick_buckets.iter()) {
+            cumulative += count;
+            buckets.push((format!("_bucket{{le=\"{}\"}}", bound), cumulative.to_string()));
+        }
+        let count: u64 = self.tick_buckets.iter().sum();
+        buckets.push((String::from("_bucket{le=\"+Inf\"}"), count.to_string()));
+        buckets.push((String::from("_sum"), self.tick_seconds.to_string()));
+        buckets.push((String::from("_count"), count.to_string()));
+        metric(
+            "tick_duration_seconds", "histogram",
+            "Time spent on a tick, without the sleep before it", &buckets
+        );
+
+        let gauge = |value: String| [(String::new(), value)];
+        metric(
+            "clients_connected", "gauge", "Connected clients, spectators included",
+            &gauge(self.clients.to_string())
+        );
+        metric("bots", "gauge", "Bots flown by the server", &gauge(self.bots.to_string()));
+        metric(
+            "sent_bytes_total", "counter", "Bytes sent to clients",
+            &gauge(BYTES_SENT.load(Ordering::Relaxed).to_string())
+        );
+        metric(
+            "received_bytes_total", "counter", "Bytes received from clients",
+            &gauge(BYTES_RECEIVED.load(Ordering::Relaxed).to_string())
+        );
+        metric(
+            "sent_bytes_per_second", "gauge", "Bytes sent to clients during the last second",
+            &gauge(self.sent_per_second.to_string())
+        );
+        metric(
+            "received_bytes_per_second", "gauge",
+            "Bytes received from clients during the last second",
+            &gauge(self.received_per_second.to_string())
+        );
+        metric(
+            "snapshot_bytes", "gauge", "Size of the game state sent to every client each tick",
+            &gauge(self.snapshot_bytes.to_string())
+        );
+        metric(
+            "projectiles", "gauge", "Bullets and missiles in flight",
+            &gauge(self.projectiles.to_string())
+        );
+        metric("lasers", "gauge", "Laser beams being fired", &gauge(self.lasers.to_string()));
+
+        let mut kills: Vec<_> = self.kills.iter()
+            .map(|(weapon, count)| {
+                (format!("{{weapon=\"{}\"}}", weapon.name().to_lowercase()), count.to_string())
+            })
+            .collect();
+        kills.sort();
+        metric("kills_total", "counter", "Kills since the server started", &kills);
+
+        metric(
+            "hurricane_active", "gauge", "1 while there is a hurricane on the map",
+            &gauge((self.hurricane_active as u8).to_string())
+        );
+        out
+    }
+}
+
+/**
+ * Metrics of the server, served over http to Prometheus
+ */
+pub struct Metrics {
+    values: Arc<Mutex<Values>>,
+    rate_start: Instant,
+    rate_sent: u64,
+    rate_received: u64,
+}
+
+impl Metrics {
+    /**
+     * Starts serving the metrics on the address in the config
+     */
+    pub fn start(config: &MetricsConfig) -> io::Result<Self> {
+        let listener = TcpListener::bind(&config.address)?;
+        info!("Serving metrics on http://{}/metrics", config.address);
+
+        let values = Arc::new(Mutex::new(Values::default()));
+        let shared = values.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let result = stream.and_then(|stream| serve(stream, &shared));
+                if let Err(e) = result {
+                    warn!("Could not serve metrics: {}", e);
+                }
+            }
+        });
+
+        Ok(Self {
+            values,
+            rate_start: Instant::now(),
+            rate_sent: 0,
+            rate_received: 0,
+        })
+    }
+
+    pub fn record_tick(
+        &mut self,
+        duration: Duration,
+        state: &GameState,
+        clients: usize,
+        bots: usize,
+        kills: &[KillEvent],
+    ) {
+        let mut values = self.values.lock().unwrap();
+        let seconds = duration.as_secs_f64();
+        let bucket = TICK_BUCKETS.iter()
+            .position(|bound| seconds <= *bound)
+            .unwrap_or(TICK_BUCKETS.len());
+        values.tick_buckets[bucket] += 1;
+        values.tick_seconds += seconds;
+
+        values.clients = clients;
+        values.bots = bots;
+        values.snapshot_bytes = bincode::serialized_size(state).unwrap_or(0);
+        values.projectiles = state.projectiles.len();
+        values.lasers = state.lasers.len();
+        values.hurricane_active = state.hurricane.is_some();
+        for kill in kills {
+            *values.kills.entry(kill.weapon).or_insert(0) += 1;
+        }
+
+        let elapsed = self.rate_start.elapsed();
+        if elapsed >= RATE_INTERVAL {
+            let sent = BYTES_SENT.load(Ordering::Relaxed);
+            let received = BYTES_RECEIVED.load(Ordering::Relaxed);
+            values.sent_per_second = (sent - self.rate_sent) as f64 / elapsed.as_secs_f64();
+            values.received_per_second =
+                (received - self.rate_received) as f64 / elapsed.as_secs_f64();
+            self.rate_start = Instant::now();
+            self.rate_sent = sent;
+            self.rate_received = received;
+        }
+    }
+}
+
+/**
+ * Answers one http request, every path but /metrics is not found
+ */
+fn serve(mut stream: TcpStream, values: &Mutex<Values>) -> io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut request = vec!();
+    let mut buffer = [0; 512];
+    while !request.ends_with(b"\r\n\r\n") && request.len() < 8192 {
+        let amount = stream.read(&mut buffer)?;
+        if amount == 0 {
+            break;
+        }
+        request.extend_from_slice(&buffer[..amount]);
+    }
+
+    let request = String::from_utf8_lossy(&request);
+    let path = request.split_whitespace().nth(1).unwrap_or("");
+    let (status, body) = if path == "/metrics" {
+        ("200 OK", values.lock().unwrap().render())
+    } else {
+        ("404 Not Found", String::from("Not found, the metrics are at /metrics\n"))
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )
+}
@@ -0,0 +1,223 @@
+use std::cmp::Reverse;
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use serde_derive::Deserialize;
+
+use libplen::gamemode::ModeName;
+use libplen::gamestate::GameState;
+use libplen::inputlog::{InputLogWriter, INPUT_LOG_EXTENSION};
+use libplen::messages::PROTOCOL_VERSION;
+use libplen::replay::{ReplayEvent, ReplayHeader, ReplayWriter, REPLAY_EXTENSION};
+
+use libplen::{error, info, warn};
+
+use crate::bans::unix_time;
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct RecordingConfig {
+    pub enabled: bool,
+    // Relative to the directory of the config file
+    pub directory: PathBuf,
+    // The oldest replays are deleted when all of them take up more than this
+    pub max_disk_mb: u64,
+    // Logs the joins and inputs of every tick to one file per server run,
+    // which `server --verify` plays again
+    pub input_log: bool,
+}
+
+impl Default for RecordingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            directory: PathBuf::from("replays"),
+            max_disk_mb: 500,
+            input_log: false,
+        }
+    }
+}
+
+struct Recording {
+    round: u32,
+    path: PathBuf,
+    writer: ReplayWriter<BufWriter<File>>,
+    start: Instant,
+    // Size of the replay when the disk cap was last checked
+    checked_bytes: u64,
+}
+
+/**
+ * Records every round to its own replay file
+ */
+pub struct Recorder {
+    directory: PathBuf,
+    max_bytes: u64,
+    mode: ModeName,
+    // The config file, stored in the header of every replay
+    config: String,
+    recording: Option<Recording>,
+    // A round whose replay was stopped because it filled the disk cap on
+    // its own, it isn't started again
+    stopped_round: Option<u32>,
+}
+
+impl Recorder {
+    pub fn new(directory: PathBuf, max_disk_mb: u64, mode: ModeName, config: String)
+        -> io::Result<Self>
+    {
+        fs::create_dir_all(&directory)?;
+        info!("Recording replays to {}", directory.display());
+        Ok(Self {
+            directory,
+            max_bytes: max_disk_mb * 1_000_000,
+            mode,
+            config,
+            recording: None,
+            stopped_round: None,
+        })
+    }
+
+    /**
+     * Sets the mode stored in the replays of the next rounds
+     */
+    pub fn set_mode(&mut self, mode: ModeName) {
+        self.mode = mode;
+    }
+
+    /**
+     * Adds a frame to the replay of the current round, starting a new file
+     * when a new round has started
+     */
+    pub fn record(&mut self, state: &GameState, events: Vec<ReplayEvent>) {
+        if self.stopped_round == Some(state.round.number) {
+            return;
+        }
+        let new_round = self.recording.as_ref()
+            .map(|recording| recording.round != state.round.number)
+            .unwrap_or(true);
+        if new_round {
+            self.finish();
+            if let Err(e) = self.start(state.round.number) {
+                error!("Could not start a replay: {}", e);
+                return;
+            }
+        }
+
+        if let Some(recording) = &mut self.recording {
+            let time = recording.start.elapsed().as_secs_f32();
+            if let Err(e) = recording.writer.write_frame(time, state, events) {
+                error!("Could not write to {}: {}", recording.path.display(), e);
+                self.recording = None;
+                return;
+            }
+
+            // Long rounds make room while they are recorded, not just when
+            // they end
+            let size = recording.writer.bytes_written();
+            if size - recording.checked_bytes >= self.max_bytes / 100 {
+                recording.checked_bytes = size;
+                if size > self.max_bytes {
+                    warn!(
+                        "Replay {} is larger than the disk cap, stopping it",
+                        recording.path.display()
+                    );
+                    self.stopped_round = Some(recording.round);
+                    self.finish();
+                } else if let Err(e) = self.enforce_disk_cap() {
+                    error!("Could not clean up old replays: {}", e);
+                }
+            }
+        }
+    }
+
+    fn start(&mut self, round: u32) -> io::Result<()> {
+        let started = unix_time();
+        let path = self.directory
+            .join(format!("{}-round{}", started, round))
+            .with_extension(REPLAY_EXTENSION);
+        let header = ReplayHeader {
+            protocol_version: PROTOCOL_VERSION,
+            mode: self.mode,
+            config: self.config.clone(),
+            started,
+            round,
+        };
+        let writer = ReplayWriter::new(BufWriter::new(File::create(&path)?), &header)?;
+        self.recording = Some(Recording {
+            round,
+            path,
+            writer,
+            start: Instant::now(),
+            checked_bytes: 0,
+        });
+        Ok(())
+    }
+
+    /**
+     * Closes the current replay, if any, and makes room for the next one
+     */
+    pub fn finish(&mut self) {
+        let recording = match self.recording.take() {
+            Some(recording) => recording,
+            None => return,
+        };
+        let size = recording.writer.bytes_written();
+        match recording.writer.finish() {
+            Ok(()) => info!("Saved replay {} ({} kB)", recording.path.display(), size / 1000),
+            Err(e) => error!("Could not save {}: {}", recording.path.display(), e),
+        }
+        if let Err(e) = self.enforce_disk_cap() {
+            error!("Could not clean up old replays: {}", e);
+        }
+    }
+
+    /**
+     * Deletes the oldest replays until the rest fit in the disk cap, along
+     * with the one being recorded
+     */
+    fn enforce_disk_cap(&self) -> io::Result<()> {
+        let current = self.recording.as_ref().map(|recording| &recording.path);
+        let mut total = self.recording.as_ref()
+            .map(|recording| recording.writer.bytes_written())
+            .unwrap_or(0);
+        let mut replays = vec!();
+        for entry in fs::read_dir(&self.directory)? {
+            let entry = entry?;
+            let path = entry.path();
+            if Some(&path) == current {
+                continue;
+            }
+            if path.extension().map(|e| e == REPLAY_EXTENSION).unwrap_or(false) {
+                let metadata = entry.metadata()?;
+                replays.push((metadata.modified()?, metadata.len(), path));
+            }
+        }
+        // Newest first
+        replays.sort_by_key(|(modified, _, _)| Reverse(*modified));
+
+        for (_, size, path) in replays {
+            total += size;
+            if total > self.max_bytes {
+                info!("Deleting old replay {}", path.display());
+                fs::remove_file(&path)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub type InputLog = InputLogWriter<BufWriter<File>>;
+
+/**
+ * Starts an input log of the game in the directory, before its first tick
+ */
+pub fn create_input_log(directory: &Path, seed: u64, state: &GameState) -> io::Result<InputLog> {
+    fs::create_dir_all(directory)?;
+    let started = unix_time();
+    let path = directory.join(started.to_string()).with_extension(INPUT_LOG_EXTENSION);
+    info!("Logging inputs to {}", path.display());
+    InputLogWriter::new(BufWriter::new(File::create(&path)?), seed, started, state)
+}
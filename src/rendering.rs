@@ -1,5 +1,5 @@
 use std::f32::consts::PI;
-use sdl2::rect::Rect;
+use sdl2::rect::{Point, Rect};
 use sdl2::render::{Canvas, Texture};
 use sdl2::video::Window;
 use libplen::constants;
@@ -64,6 +64,21 @@ pub fn draw_texture_rotated_and_scaled(
     canvas.copy_ex(texture, None, dest_rect, angle, None, false, false)
 }
 
+pub fn draw_circle(
+    canvas: &mut Canvas<Window>, center: Vec2, radius: f32, color: (u8, u8, u8, u8)
+) -> Result<(), String> {
+    const SEGMENTS: usize = 64;
+    let points: Vec<Point> = (0..=SEGMENTS)
+        .map(|i| {
+            let angle = i as f32 / SEGMENTS as f32 * PI * 2.;
+            let point = center + Vec2::from_direction(angle, radius);
+            Point::new(point.x as i32, point.y as i32)
+        })
+        .collect();
+    canvas.set_draw_color(color);
+    canvas.draw_lines(points.as_slice())
+}
+
 pub fn setup_coordinates(canvas: &mut Canvas<Window>) -> Result<(), String> {
     let (window_width, window_height) = canvas.window().size();
     let (w, h) = if window_width < window_height {
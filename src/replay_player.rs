@@ -0,0 +1,306 @@
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use sdl2::keyboard::{Keycode, KeyboardState};
+use sdl2::rect::Rect;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+
+use libplen::constants;
+use libplen::gamestate::GameState;
+use libplen::math::vec2;
+use libplen::error;
+use libplen::replay::{ReplayEvent, ReplayFrame, ReplayHeader, ReplayReader, REPLAY_EXTENSION};
+
+use crate::assets::Assets;
+use crate::camera::SpectatorCamera;
+use crate::hud;
+use crate::map::Map;
+use crate::{play_sound_effect, StateResult};
+
+const SPEEDS: [f32; 6] = [0.25, 0.5, 1., 2., 4., 8.];
+// Seconds skipped by the arrow keys
+const SEEK_STEP: f32 = 5.;
+const TIMELINE_MARGIN: f32 = 20.;
+const TIMELINE_Y: f32 = 70.;
+const TIMELINE_HEIGHT: f32 = 12.;
+
+/**
+ * Directory the replay list in the menu is read from
+ */
+pub fn replay_directory() -> PathBuf {
+    std::env::var("PLEN_REPLAYS")
+        .map(PathBuf::from)
+        .unwrap_or(PathBuf::from("replays"))
+}
+
+/**
+ * The replays in the replay directory, newest first
+ */
+pub fn list_replays() -> Vec<PathBuf> {
+    let mut replays: Vec<_> = std::fs::read_dir(replay_directory())
+        .map(|entries| {
+            entries.filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .filter(|path| path.extension().map(|e| e == REPLAY_EXTENSION).unwrap_or(false))
+                .collect()
+        })
+        .unwrap_or(vec!());
+    // The file names start with the unix time
+    replays.sort();
+    replays.reverse();
+    replays
+}
+
+pub struct ReplayPlayer {
+    header: ReplayHeader,
+    // The frames are kept encoded, they are decoded as playback reaches them
+    frames: Vec<ReplayFrame>,
+    frame_index: usize,
+    state_bytes: Vec<u8>,
+    game_state: GameState,
+    time: f32,
+    speed_selection: usize,
+    paused: bool,
+    camera: SpectatorCamera,
+    map: Map,
+    // Times of all deaths, shown on the timeline
+    kill_times: Vec<f32>,
+    last_time: Instant,
+    powerup_rotation: f32,
+    hit_effect_timer: f32,
+}
+
+impl ReplayPlayer {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let mut reader = ReplayReader::new(BufReader::new(File::open(path)?))?;
+        let mut frames = vec!();
+        while let Some(frame) = reader.next_raw_frame()? {
+            frames.push(frame);
+        }
+        if !frames.first().map(|frame| frame.snapshot.is_full()).unwrap_or(false) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Replay has no frames"));
+        }
+
+        let kill_times = frames.iter()
+            .filter(|frame| {
+                frame.events.iter().any(|event| matches!(event, ReplayEvent::Death(_)))
+            })
+            .map(|frame| frame.time)
+            .collect();
+
+        let mut map = Map::new();
+        map.full_info = true;
+        let mut player = Self {
+            header: reader.header,
+            frames,
+            frame_index: 0,
+            state_bytes: vec!(),
+            game_state: GameState::new(),
+            time: 0.,
+            speed_selection: 2,
+            paused: false,
+            camera: SpectatorCamera::new(),
+            map,
+            kill_times,
+            last_time: Instant::now(),
+            powerup_rotation: 0.,
+            hit_effect_timer: 0.,
+        };
+        player.decode_frame(0)?;
+        player.update_game_state();
+        Ok(player)
+    }
+
+    fn duration(&self) -> f32 {
+        self.frames.last().map(|frame| frame.time).unwrap_or(0.)
+    }
+
+    fn viewed_id(&self) -> u64 {
+        // Nobody has this id, so nobody's view is shown when flying freely
+        self.camera.following.unwrap_or(u64::MAX)
+    }
+
+    fn decode_frame(&mut self, index: usize) -> io::Result<()> {
+        self.state_bytes = self.frames[index].snapshot.decode(&self.state_bytes)?;
+        self.frame_index = index;
+        Ok(())
+    }
+
+    fn update_game_state(&mut self) {
+        match bincode::deserialize(&self.state_bytes) {
+            Ok(state) => self.game_state = state,
+            Err(e) => error!("Could not decode replay frame {}: {}", self.frame_index, e),
+        }
+    }
+
+    /**
+     * Jumps to a time, starting from the closest full snapshot before it
+     */
+    fn seek(&mut self, time: f32) {
+        self.time = time.max(0.).min(self.duration());
+        let target = self.frames.iter()
+            .rposition(|frame| frame.time <= self.time)
+            .unwrap_or(0);
+        let keyframe = self.frames[..=target].iter()
+            .rposition(|frame| frame.snapshot.is_full())
+            .unwrap_or(0);
+        let start = if target >= self.frame_index && self.frame_index >= keyframe {
+            self.frame_index + 1
+        } else {
+            keyframe
+        };
+        for index in start..=target {
+            if let Err(e) = self.decode_frame(index) {
+                error!("Could not decode replay frame {}: {}", index, e);
+                return;
+            }
+        }
+        self.update_game_state();
+    }
+
+    pub fn update(&mut self, assets: &Assets, keyboard_state: &KeyboardState) -> StateResult {
+        let elapsed = self.last_time.elapsed();
+        self.last_time = Instant::now();
+        let dt_duration = std::time::Duration::from_millis(1000 / 60);
+        if elapsed < dt_duration {
+            std::thread::sleep(dt_duration - elapsed);
+        }
+        let delta_time = elapsed.as_secs_f32();
+
+        if !self.paused {
+            self.time = (self.time + delta_time * SPEEDS[self.speed_selection]).min(self.duration());
+            while self.frame_index + 1 < self.frames.len()
+                && self.frames[self.frame_index + 1].time <= self.time
+            {
+                if let Err(e) = self.decode_frame(self.frame_index + 1) {
+                    error!("Could not decode replay frame {}: {}", self.frame_index + 1, e);
+                    return StateResult::GotoNext;
+                }
+                let viewed_id = self.viewed_id();
+                for event in &self.frames[self.frame_index].events {
+                    match event {
+                        ReplayEvent::Sound(sound, position) => {
+                            play_sound_effect(*sound, *position, assets, &mut self.map);
+                        }
+                        ReplayEvent::PlayerHit(id) if *id == viewed_id => {
+                            self.hit_effect_timer = constants::HIT_SEQUENCE_AMOUNT;
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            self.update_game_state();
+        }
+
+        self.hit_effect_timer = (self.hit_effect_timer - constants::HIT_SEQUENCE_RATE).max(0.);
+        self.camera.update(delta_time, &self.game_state, Some(keyboard_state));
+        self.map.update(delta_time, &self.game_state, self.viewed_id());
+        self.powerup_rotation += constants::POWERUP_SPEED * delta_time;
+        StateResult::Continue
+    }
+
+    pub fn key_down_event(&mut self, keycode: Keycode) -> StateResult {
+        match keycode {
+            Keycode::P => self.paused = !self.paused,
+            Keycode::Left => self.seek(self.time - SEEK_STEP),
+            Keycode::Right => self.seek(self.time + SEEK_STEP),
+            Keycode::Up => {
+                self.speed_selection = (self.speed_selection + 1).min(SPEEDS.len() - 1);
+            }
+            Keycode::Down => self.speed_selection = self.speed_selection.saturating_sub(1),
+            Keycode::Space => self.camera.follow_next_player(&self.game_state),
+            Keycode::F => self.camera.following = None,
+            Keycode::Escape => return StateResult::GotoNext,
+            _ => {}
+        }
+        StateResult::Continue
+    }
+
+    fn timeline_rect(canvas: &Canvas<Window>) -> Rect {
+        let width = canvas.logical_size().0 as f32 - TIMELINE_MARGIN * 2.;
+        Rect::new(
+            TIMELINE_MARGIN as i32,
+            TIMELINE_Y as i32,
+            width as u32,
+            TIMELINE_HEIGHT as u32
+        )
+    }
+
+    /**
+     * Seeks to the clicked time on the timeline
+     */
+    pub fn mouse_button_down_event(&mut self, x: f32, y: f32, canvas: &Canvas<Window>) {
+        let rect = Self::timeline_rect(canvas);
+        let margin = 10.;
+        if y >= rect.top() as f32 - margin && y <= rect.bottom() as f32 + margin {
+            let fraction = (x - rect.left() as f32) / rect.width() as f32;
+            if (0. ..=1.).contains(&fraction) {
+                self.seek(fraction * self.duration());
+            }
+        }
+    }
+
+    pub fn draw(&mut self, canvas: &mut Canvas<Window>, assets: &mut Assets) -> Result<(), String> {
+        self.map.draw(
+            self.viewed_id(),
+            canvas,
+            self.camera.position,
+            &self.game_state,
+            assets,
+            self.powerup_rotation,
+            self.hit_effect_timer,
+            &self.game_state.hurricane
+        )?;
+
+        hud::draw_round_status(canvas, assets, &self.game_state.round)?;
+        hud::draw_team_scores(canvas, assets, &self.game_state)?;
+        hud::draw_control_bar(canvas, assets, &self.game_state)?;
+        hud::draw_battle_royale(canvas, assets, &self.game_state)?;
+        hud::draw_race(canvas, assets, &self.game_state, self.viewed_id())?;
+        hud::draw_spectator(
+            canvas, assets, &self.game_state, self.camera.following,
+            "P: pause   Arrows: seek and speed   Space: next player   F: free camera"
+        )?;
+
+        self.draw_timeline(canvas, assets)
+    }
+
+    fn draw_timeline(&self, canvas: &mut Canvas<Window>, assets: &Assets) -> Result<(), String> {
+        let rect = Self::timeline_rect(canvas);
+        let duration = self.duration().max(0.001);
+        canvas.set_draw_color((0, 0, 0, 150));
+        canvas.fill_rect(rect)?;
+        canvas.set_draw_color((255, 255, 255, 200));
+        canvas.fill_rect(Rect::new(
+            rect.left(),
+            rect.top(),
+            (rect.width() as f32 * self.time / duration) as u32,
+            rect.height()
+        ))?;
+
+        canvas.set_draw_color((255, 60, 60, 255));
+        for time in &self.kill_times {
+            let x = rect.left() + (rect.width() as f32 * time / duration) as i32;
+            canvas.fill_rect(Rect::new(x - 1, rect.top() - 4, 3, rect.height() + 8))?;
+        }
+
+        let status = if self.paused {String::from("Paused")} else {
+            format!("{}x", SPEEDS[self.speed_selection])
+        };
+        let text = format!(
+            "{} round {}   {} / {}   {}",
+            self.header.mode.name(),
+            self.header.round,
+            hud::format_time(self.time),
+            hud::format_time(self.duration()),
+            status
+        );
+        hud::draw_text(
+            canvas, assets, &text, (255, 255, 255, 255),
+            vec2(canvas.logical_size().0 as f32 * 0.5, rect.bottom() as f32 + 15.)
+        )
+    }
+}
@@ -1,27 +1,57 @@
+mod admin;
+mod bans;
+mod chat_commands;
+mod config;
+mod metrics;
+mod recording;
+mod stats_store;
+mod votes;
+
+use std::collections::{HashSet, VecDeque};
 use std::io;
 use std::vec;
 use std::io::prelude::*;
-use std::net::TcpStream;
+use std::net::{Shutdown, SocketAddr, TcpStream};
 use std::net::TcpListener;
-use std::time::Instant;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
 
-use rand::Rng;
 use unicode_truncate::UnicodeTruncateStr;
 
 use libplen::messages::{
+    ChatChannel,
     ClientMessage,
     ClientInput,
     MessageReader,
     ServerMessage,
     SoundEffect
 };
-use libplen::player::Player;
+use libplen::battle_royale::BattleRoyale;
+use libplen::bot::{Bot, BotConfig, Profile};
+use libplen::ctf::CaptureTheFlag;
+use libplen::gamemode::{FreeForAll, GameModeKind, ModeName};
+use libplen::koth::KingOfTheHill;
 use libplen::powerups::PowerUpKind;
 use libplen::gamestate;
+use libplen::round::{Round, RoundPhase};
+use libplen::scoreboard::KillEvent;
 use libplen::constants;
 use libplen::debug;
-use libplen::projectiles::Projectile;
 use libplen::math::{Vec2, vec2};
+use libplen::race::Race;
+use libplen::replay::ReplayEvent;
+use libplen::inputlog::{self, StateOp, Verification};
+use libplen::log;
+use libplen::{error, info, warn};
+use admin::{AdminCommand, BotSetting};
+use bans::{Ban, BanKind, BanList, ModerationLog};
+use chat_commands::{ChatCommand, Role, VoteCommand};
+use config::ServerConfig;
+use votes::{VoteKind, VoteUpdate, Votes};
+use metrics::Metrics;
+use recording::{create_input_log, InputLog, Recorder};
+use stats_store::StatsStore;
 
 fn send_bytes(bytes: &[u8], stream: &mut TcpStream) -> io::Result<()> {
     let mut start = 0;
@@ -52,13 +82,128 @@ fn send_server_message(msg: &ServerMessage, stream: &mut TcpStream)
     let data = bincode::serialize(msg).expect("Failed to encode message");
     let length = data.len() as u16;
     send_bytes(&length.to_be_bytes(), stream)?;
-    send_bytes(&data, stream)
+    send_bytes(&data, stream)?;
+    metrics::count_sent(2 + data.len());
+    Ok(())
 }
 
 struct Client {
     id: u64,
+    address: SocketAddr,
+    // Name of the last game joined by this client
+    name: Option<String>,
+    // Spectators get the game state but have no plane
+    spectating: bool,
     message_reader: MessageReader,
     input: ClientInput,
+    chat_limit: ChatLimit,
+    role: Role,
+    // Clients whose chat messages this client doesn't get
+    ignored: HashSet<u64>,
+}
+
+impl Client {
+    /**
+     * The name chat messages are sent with, also for spectators that never
+     * joined
+     */
+    fn chat_name(&self) -> String {
+        self.name.clone().unwrap_or(format!("Spectator {}", self.id))
+    }
+}
+
+/**
+ * Remembers when the last few chat messages of a client were sent
+ */
+struct ChatLimit {
+    times: VecDeque<Instant>,
+}
+
+impl ChatLimit {
+    fn new() -> Self {
+        Self { times: VecDeque::new() }
+    }
+
+    /**
+     * Counts a message, returns false if too many were sent lately
+     */
+    fn allow(&mut self) -> bool {
+        let window = Duration::from_secs(constants::CHAT_RATE_WINDOW);
+        while self.times.front().map(|time| time.elapsed() > window).unwrap_or(false) {
+            self.times.pop_front();
+        }
+        if self.times.len() >= constants::CHAT_RATE_LIMIT {
+            return false;
+        }
+        self.times.push_back(Instant::now());
+        true
+    }
+}
+
+/**
+ * Returns the text after the name if the text starts with it, ignoring case,
+ * followed by whitespace or nothing
+ */
+fn strip_name<'a>(text: &'a str, name: &str) -> Option<&'a str> {
+    let mut chars = text.char_indices();
+    for expected in name.chars() {
+        let (_, c) = chars.next()?;
+        if !c.to_lowercase().eq(expected.to_lowercase()) {
+            return None;
+        }
+    }
+    let rest = chars.as_str();
+    if rest.chars().next().map(char::is_whitespace).unwrap_or(true) {
+        Some(rest)
+    } else {
+        None
+    }
+}
+
+/**
+ * A chat message from the server to a player
+ */
+fn notice(text: &str) -> ServerMessage {
+    ServerMessage::Chat {
+        sender: None,
+        name: String::new(),
+        channel: ChatChannel::All,
+        time: bans::unix_time(),
+        text: text.to_string(),
+    }
+}
+
+/**
+ * Sends a chat message from a player to the clients picked by id that don't
+ * ignore the sender
+ */
+fn send_chat(
+    connections: &mut [Client],
+    sender: u64,
+    message: &ServerMessage,
+    recipient: impl Fn(u64) -> bool
+) {
+    for client in connections {
+        if recipient(client.id) && !client.ignored.contains(&sender) {
+            // Broken connections are found when the next game state is sent
+            let _ = send_server_message(message, &mut client.message_reader.stream);
+        }
+    }
+}
+
+/**
+ * Removes control characters and surrounding whitespace, and cuts the
+ * message to the maximum length. Returns None if nothing is left.
+ */
+fn clean_chat_message(text: &str) -> Option<String> {
+    let text: String = text.chars()
+        .filter(|c| !c.is_control())
+        .collect::<String>()
+        .trim()
+        .chars()
+        .take(constants::CHAT_MAX_LENGTH)
+        .collect();
+    if text.is_empty() {None} else {Some(text)}
 }
 
 struct Server {
@@ -66,26 +211,97 @@ struct Server {
     connections: Vec<Client>,
     state: gamestate::GameState,
     next_id: u64,
-    last_time: Instant,
-    debug_channel: std::sync::mpsc::Receiver<debug::DebugLine>
+    // When the next tick should run
+    next_tick: Instant,
+    debug_channel: std::sync::mpsc::Receiver<debug::DebugLine>,
+    admin_channel: std::sync::mpsc::Receiver<AdminCommand>,
+    bans: BanList,
+    moderation_log: ModerationLog,
+    stats_store: StatsStore,
+    bots: Vec<Bot>,
+    bot_config: BotConfig,
+    admin_password: Option<String>,
+    votes: Votes,
+    // Modes that are voted for are created from this
+    config: ServerConfig,
+    // The simulation seed, bots get their own random numbers from it
+    seed: u64,
+    recorder: Option<Recorder>,
+    input_log: Option<InputLog>,
+    metrics: Option<Metrics>,
 }
 
 impl Server {
-    pub fn new() -> Self {
-        let listener = TcpListener::bind("0.0.0.0:4444")
+    pub fn new(config: ServerConfig) -> Self {
+        let listener = TcpListener::bind(&config.address)
             .unwrap();
 
         listener.set_nonblocking(true).unwrap();
 
-        println!("Listening on 0.0.0.0:4444");
+        info!(address = config.address; "Listening for connections");
+
+        let bans = BanList::load(config.resolve(&config.ban_file))
+            .expect("Could not load ban list");
+
+        let stats_store = StatsStore::load(config.resolve(&config.stats_file))
+            .expect("Could not load player stats");
+
+        let seed = config.seed.unwrap_or_else(rand::random);
+        info!("Simulation seed {}", seed);
+        let mut state = gamestate::GameState::with_seed(seed);
+        state.round = Round::new(config.round.clone());
+        state.teams = config.teams.clone();
+        state.set_mode(create_mode(&config, &stats_store));
+        info!("Playing {}", config.mode.name().to_lowercase());
+        state.spawn_points = config.spawn_points.iter()
+            .map(|(x, y)| vec2(*x, *y))
+            .collect();
+
+        let recorder = if config.recording.enabled {
+            let recorder = Recorder::new(
+                config.resolve(&config.recording.directory),
+                config.recording.max_disk_mb,
+                config.mode,
+                config.source.clone()
+            );
+            Some(recorder.expect("Could not create the replay directory"))
+        } else {
+            None
+        };
+
+        let input_log = if config.recording.input_log {
+            let directory = config.resolve(&config.recording.directory);
+            Some(create_input_log(&directory, seed, &state).expect("Could not create the input log"))
+        } else {
+            None
+        };
+
+        let metrics = if config.metrics.enabled {
+            Some(Metrics::start(&config.metrics).expect("Could not serve metrics"))
+        } else {
+            None
+        };
 
         Self {
             listener,
             connections: vec!(),
             next_id: 0,
-            last_time: Instant::now(),
-            state: gamestate::GameState::new(),
+            next_tick: Instant::now(),
+            state,
             debug_channel: debug::init_debug_channel(),
+            admin_channel: admin::init_admin_console(),
+            bans,
+            moderation_log: ModerationLog::new(config.resolve(&config.moderation_log)),
+            stats_store,
+            bots: vec!(),
+            bot_config: config.bots.clone(),
+            admin_password: config.admin_password.clone(),
+            votes: Votes::new(config.votes.clone()),
+            recorder,
+            input_log,
+            metrics,
+            config,
+            seed,
         }
     }
 
@@ -108,23 +324,192 @@ impl Server {
     }
 
     pub fn update(&mut self) {
-        let elapsed = self.last_time.elapsed();
-        let delta_time = self.get_delta_time();
-        let dt_duration = std::time::Duration::from_millis(constants::SERVER_SLEEP_DURATION);
-        if elapsed < dt_duration {
-            std::thread::sleep(dt_duration - elapsed);
+        // Every tick moves the game forward by the same step. A server that
+        // falls behind runs the late ticks right away instead of slowing
+        // the game down.
+        let now = Instant::now();
+        if now < self.next_tick {
+            std::thread::sleep(self.next_tick - now);
+        } else if now - self.next_tick > Duration::from_millis(constants::SERVER_MAX_TICK_BACKLOG) {
+            self.next_tick = now;
         }
-        self.last_time = Instant::now();
+        self.next_tick += Duration::from_millis(constants::SERVER_SLEEP_DURATION);
+        let tick_start = Instant::now();
+        let delta_time = self.get_delta_time();
 
-        let (hit_players, hit_powerup_positions, fired_laser_positions) = 
+        let round_phase = self.state.round.phase;
+        let (hit_players, hit_powerup_positions, fired_laser_positions, kills) =
             self.state.update(delta_time);
+        log_op(&mut self.input_log, StateOp::Update { delta: delta_time });
+
+        self.record_kills(&kills);
+        self.record_laps();
+        if self.state.round.phase != round_phase && self.state.round.phase == RoundPhase::Ended {
+            let bots: Vec<_> = self.bots.iter().map(|bot| bot.id).collect();
+            self.stats_store.record_round(
+                &self.state.scoreboard, &self.state.round.winners, &bots
+            );
+        }
+        self.stats_store.save_if_due();
 
         self.accept_new_connections();
-        self.update_clients(
+        self.handle_admin_commands();
+        self.update_votes(false);
+        self.manage_bots();
+        let sounds = self.update_clients(
             delta_time, &hit_players, &hit_powerup_positions, &fired_laser_positions
         );
 
+        // Empty servers are not worth recording
+        if let Some(recorder) = &mut self.recorder {
+            if self.connections.is_empty() {
+                recorder.finish();
+            } else {
+                let events = sounds.into_iter()
+                    .map(|(sound, position)| ReplayEvent::Sound(sound, position))
+                    .chain(hit_players.iter().map(|id| ReplayEvent::PlayerHit(*id)))
+                    .chain(kills.iter().map(|kill| ReplayEvent::Death(*kill)))
+                    .collect();
+                recorder.record(&self.state, events);
+            }
+        }
+
+        if let Some(input_log) = &mut self.input_log {
+            if let Err(e) = input_log.end_tick(&self.state) {
+                error!("Could not write to the input log, stopping it: {}", e);
+                self.input_log = None;
+            }
+        }
+
         self.state.update_debug_lines(&self.debug_channel);
+
+        if let Some(metrics) = &mut self.metrics {
+            metrics.record_tick(
+                tick_start.elapsed(), &self.state, self.connections.len(), self.bots.len(), &kills
+            );
+        }
+    }
+
+    /**
+     * The clients with a plane, alive or dead. Spectators and clients in
+     * the menu are left out.
+     */
+    fn playing_clients(&self) -> Vec<u64> {
+        let state = &self.state;
+        self.connections.iter()
+            .map(|client| client.id)
+            .filter(|id| {
+                state.get_player_by_id(*id).is_some() || state.get_dead_player_by_id(*id).is_some()
+            })
+            .collect()
+    }
+
+    fn is_bot(&self, id: u64) -> bool {
+        self.bots.iter().any(|bot| bot.id == id)
+    }
+
+    /**
+     * Adds bots until the game has as many players as configured, and
+     * removes one for every human that joins
+     */
+    fn manage_bots(&mut self) {
+        // Spectators have no plane, so they don't take a slot
+        let humans = self.playing_clients().len();
+        let wanted = self.bot_config.count.saturating_sub(humans);
+
+        while self.bots.len() < wanted {
+            let profiles = &self.bot_config.profiles;
+            let profile = match profiles.len() {
+                0 => Profile::Dogfighter,
+                n => profiles[self.bots.len() % n],
+            };
+            let mut bot = Bot::new(self.next_id, self.bot_config.difficulty, profile, self.seed);
+            self.next_id += 1;
+            let (name, plane, color) = bot.pick_identity(&self.state);
+            info!(
+                client = bot.id, name = name;
+                "Adding {} {} bot", bot.difficulty.name(), bot.profile.name()
+            );
+            log_op(&mut self.input_log, StateOp::Join {
+                id: bot.id, name: name.clone(), plane, color: color.clone()
+            });
+            self.state.join(bot.id, name, plane, color);
+            self.bots.push(bot);
+        }
+        while self.bots.len() > wanted {
+            let bot = self.bots.pop().unwrap();
+            if let Some(player) = self.state.get_player_by_id(bot.id) {
+                let msg = format!("{} has left the game.", player.name);
+                self.state.killfeed.add_message(&msg);
+            }
+            self.state.remove_player(bot.id);
+            log_op(&mut self.input_log, StateOp::Leave { id: bot.id });
+        }
+    }
+
+    /**
+     * Flies the bots and respawns the dead ones. Returns the sounds to play.
+     */
+    fn update_bots(&mut self, delta_time: f32) -> Vec<(SoundEffect, Vec2)> {
+        let mut sounds = vec!();
+        for bot in &mut self.bots {
+            let plane = self.state.get_dead_player_by_id(bot.id).map(|dead| dead.player.planetype);
+            if let Some(plane) = plane {
+                self.state.respawn_player(bot.id, plane);
+                log_op(&mut self.input_log, StateOp::Respawn { id: bot.id, plane });
+                continue;
+            }
+
+            let input = bot.update(&self.state, delta_time);
+            if let Some(input_log) = &mut self.input_log {
+                input_log.fly(bot.id, &input);
+            }
+            let bullet = self.state.apply_input(bot.id, &input, delta_time, &mut sounds);
+            if let Some(bullet) = bullet {
+                self.state.fire(bot.id, bullet, &mut sounds);
+            }
+        }
+        sounds
+    }
+
+    fn record_laps(&mut self) {
+        let laps = match &mut self.state.mode {
+            GameModeKind::Race(race) => race.take_laps(),
+            _ => return,
+        };
+        for lap in laps {
+            if self.is_bot(lap.player) {
+                continue;
+            }
+            if let Some(player) = self.state.get_player_by_id(lap.player) {
+                self.stats_store.record_lap(&player.name, lap.plane, lap.time);
+            }
+        }
+    }
+
+    fn record_kills(&mut self, kills: &[KillEvent]) {
+        // Bots don't get lifetime stats
+        for kill in kills {
+            let victim = match self.state.get_player_by_id(kill.victim) {
+                Some(_) if self.is_bot(kill.victim) => None,
+                Some(victim) => Some(victim.name.clone()),
+                None => continue,
+            };
+            let killer = if kill.killer == kill.victim || self.is_bot(kill.killer) {
+                None
+            } else {
+                // The killer can be dead already, like when a missile hits
+                // after its shooter died
+                self.state.get_player_by_id(kill.killer)
+                    .or(self.state.get_dead_player_by_id(kill.killer).map(|dead| &dead.player))
+                    .map(|killer| (killer.name.clone(), killer.planetype))
+            };
+            self.stats_store.record_death(
+                killer.as_ref().map(|(name, plane)| (name.as_str(), *plane)),
+                victim.as_deref(),
+                kill.weapon
+            );
+        }
     }
 
     fn accept_new_connections(&mut self) {
@@ -132,19 +517,38 @@ impl Server {
         for stream in self.listener.incoming() {
             match stream {
                 Ok(mut stream) => {
+                    let address = match stream.peer_addr() {
+                        Ok(address) => address,
+                        Err(_) => continue,
+                    };
+                    if let Some(ban) = self.bans.find_address_ban(address.ip()) {
+                        warn!(address = address; "Refused connection from banned address");
+                        let _ = send_server_message(
+                            &ServerMessage::Kicked(ban.reason.clone()),
+                            &mut stream
+                        );
+                        continue;
+                    }
+
                     stream.set_nonblocking(true).unwrap();
-                    println!("Got new connection {}", self.next_id);
+                    info!(client = self.next_id, address = address; "Got new connection");
                     if let Err(_) = send_server_message(
                         &ServerMessage::AssignId(self.next_id),
                         &mut stream
                     ) {
-                        println!("Could not send assign id message");
+                        warn!(client = self.next_id, address = address; "Could not send assign id message");
                         continue;
                     }
                     self.connections.push(Client {
                         id: self.next_id,
+                        address,
+                        name: None,
+                        spectating: false,
                         message_reader: MessageReader::new(stream),
                         input: ClientInput::new(),
+                        chat_limit: ChatLimit::new(),
+                        role: Role::Player,
+                        ignored: HashSet::new(),
                     });
                     self.next_id += 1;
                 }
@@ -158,25 +562,518 @@ impl Server {
         }
     }
 
+    fn handle_admin_commands(&mut self) {
+        let commands: Vec<_> = self.admin_channel.try_iter().collect();
+        for command in commands {
+            match command {
+                AdminCommand::Players => {
+                    for client in &self.connections {
+                        let name = match self.state.get_player_by_id(client.id) {
+                            Some(player) => player.name.clone(),
+                            None if client.spectating => String::from("<spectating>"),
+                            None => String::from("<in menu>"),
+                        };
+                        println!("{}\t{}\t{}", client.id, client.address, name);
+                    }
+                }
+                AdminCommand::Kick { id, reason } => {
+                    if !self.kick_client(id, &reason, "console") {
+                        println!("No client with id {}", id);
+                    }
+                }
+                AdminCommand::Ban { kind, target, duration, reason } => {
+                    self.ban(kind, target, duration, &reason, "console");
+                }
+                AdminCommand::BanId { id, duration, reason } => {
+                    let address = self.connections.iter()
+                        .find(|client| client.id == id)
+                        .map(|client| client.address.ip());
+                    match address {
+                        Some(address) => {
+                            self.ban(BanKind::Address, address.to_string(), duration, &reason, "console");
+                        }
+                        None => println!("No client with id {}", id),
+                    }
+                }
+                AdminCommand::Unban { target } => {
+                    if self.bans.remove(&target) {
+                        self.moderation_log.record("console", "unban", &target, "");
+                        println!("Removed bans on {}", target);
+                    } else {
+                        println!("No bans on {}", target);
+                    }
+                }
+                AdminCommand::Bans => {
+                    let now = bans::unix_time();
+                    for ban in self.bans.bans() {
+                        let expiry = ban.expires
+                            .map(|t| format!("{}s left", t.saturating_sub(now)))
+                            .unwrap_or(String::from("permanent"));
+                        println!("{}\t{}\tby {}\t{}", ban.target, expiry, ban.source, ban.reason);
+                    }
+                }
+                AdminCommand::Stats { name: Some(name) } => {
+                    match self.stats_store.get(&name) {
+                        Some(stats) => {
+                            println!(
+                                "{}: {} kills, {} deaths, {} assists, {} rounds won of {}",
+                                name, stats.kills, stats.deaths, stats.assists,
+                                stats.rounds_won, stats.rounds_played
+                            );
+                            for (plane, kills) in &stats.kills_per_plane {
+                                println!("  {}: {} kills", plane.name(), kills);
+                            }
+                            if let Some(weapon) = stats.favourite_weapon() {
+                                println!("  favourite weapon: {}", weapon.name());
+                            }
+                            for (week, week_stats) in stats.recent_weeks(bans::unix_time(), 8) {
+                                println!(
+                                    "  week {}: {} kills, {} deaths",
+                                    week, week_stats.kills, week_stats.deaths
+                                );
+                            }
+                        }
+                        None => println!("No stats for {}", name),
+                    }
+                }
+                AdminCommand::Stats { name: None } => {
+                    for (name, stats) in self.stats_store.top(constants::LIFETIME_STATS_COUNT) {
+                        println!(
+                            "{}\t{} kills\t{} deaths\tK/D {:.2}",
+                            name, stats.kills, stats.deaths, stats.kill_death_ratio()
+                        );
+                    }
+                }
+                AdminCommand::Bots => {
+                    for bot in &self.bots {
+                        let name = self.state.get_player_by_id(bot.id)
+                            .or(self.state.get_dead_player_by_id(bot.id).map(|dead| &dead.player))
+                            .map(|p| p.name.clone())
+                            .unwrap_or_default();
+                        println!(
+                            "{}\t{}\t{}\t{}",
+                            bot.id, name, bot.difficulty.name(), bot.profile.name()
+                        );
+                    }
+                }
+                AdminCommand::SetBot { id, setting } => {
+                    let bots = self.bots.iter_mut()
+                        .filter(|bot| id.map(|id| bot.id == id).unwrap_or(true));
+                    let mut changed = 0;
+                    for bot in bots {
+                        match setting {
+                            BotSetting::Difficulty(difficulty) => bot.difficulty = difficulty,
+                            BotSetting::Profile(profile) => bot.profile = profile,
+                        }
+                        changed += 1;
+                    }
+                    // New bots get the setting too when it applies to all
+                    if id.is_none() {
+                        match setting {
+                            BotSetting::Difficulty(difficulty) => self.bot_config.difficulty = difficulty,
+                            BotSetting::Profile(profile) => self.bot_config.profiles = vec!(profile),
+                        }
+                    }
+                    println!("Changed {} bots", changed);
+                }
+                AdminCommand::Help => println!("{}", admin::HELP),
+            }
+        }
+    }
+
+    /**
+     * Adds a ban and kicks every connected client it applies to
+     */
+    fn ban(
+        &mut self,
+        kind: BanKind,
+        target: String,
+        duration: Option<u64>,
+        reason: &str,
+        source: &str
+    ) {
+        let now = bans::unix_time();
+        self.bans.add(Ban {
+            kind,
+            target: target.clone(),
+            reason: reason.to_string(),
+            source: source.to_string(),
+            created: now,
+            expires: duration.map(|d| now.saturating_add(d)),
+        });
+        let action = match duration {
+            Some(d) => format!("ban {}s", d),
+            None => String::from("ban"),
+        };
+        self.moderation_log.record(source, &action, &target, reason);
+        info!(source = source; "Banned {}", target);
+
+        let banned_clients: Vec<_> = self.connections.iter()
+            .filter(|client| {
+                let name = self.state.get_player_by_id(client.id).map(|p| p.name.as_str());
+                match kind {
+                    BanKind::Address => target.parse::<bans::IpNet>()
+                        .map(|net| net.contains(client.address.ip()))
+                        .unwrap_or(false),
+                    BanKind::Name => name
+                        .map(|n| n.to_lowercase() == target.to_lowercase())
+                        .unwrap_or(false),
+                }
+            })
+            .map(|client| client.id)
+            .collect();
+        for id in banned_clients {
+            self.disconnect_client(id, reason);
+        }
+    }
+
+    /**
+     * Kicks a client and records it in the moderation log. Returns false if
+     * there is no such client
+     */
+    fn kick_client(&mut self, id: u64, reason: &str, source: &str) -> bool {
+        let name = self.state.get_player_by_id(id)
+            .map(|p| p.name.clone())
+            .unwrap_or(format!("client {}", id));
+        if self.disconnect_client(id, reason) {
+            self.moderation_log.record(source, "kick", &name, reason);
+            info!(client = id, name = name, source = source; "Kicked client");
+            true
+        } else {
+            false
+        }
+    }
+
+    fn disconnect_client(&mut self, id: u64, reason: &str) -> bool {
+        let client = match self.connections.iter_mut().find(|client| client.id == id) {
+            Some(client) => client,
+            None => return false,
+        };
+        let _ = send_server_message(
+            &ServerMessage::Kicked(reason.to_string()),
+            &mut client.message_reader.stream
+        );
+        let _ = client.message_reader.stream.shutdown(Shutdown::Both);
+
+        self.state.remove_player(id);
+        log_op(&mut self.input_log, StateOp::Leave { id });
+        self.connections.retain(|client| client.id != id);
+        true
+    }
+
+    fn send_notice(&mut self, id: u64, text: &str) {
+        if let Some(client) = self.connections.iter_mut().find(|client| client.id == id) {
+            let _ = send_server_message(&notice(text), &mut client.message_reader.stream);
+        }
+    }
+
+    fn announce(&mut self, text: &str) {
+        send_chat(&mut self.connections, u64::MAX, &notice(text), |_| true);
+    }
+
+    /**
+     * Finds the connected player whose name the text starts with, the
+     * longest name wins. Returns their id and name, and the rest of the text.
+     */
+    fn find_client_by_name(&self, text: &str) -> Option<(u64, String, String)> {
+        self.connections.iter()
+            .filter_map(|client| {
+                let name = client.name.as_ref()?;
+                let rest = strip_name(text, name)?;
+                Some((client.id, name, rest))
+            })
+            .max_by_key(|(_, name, _)| name.chars().count())
+            .map(|(id, name, rest)| (id, name.clone(), rest.trim().to_string()))
+    }
+
+    /**
+     * Sends a chat message on to everyone, or runs it if it is a command
+     */
+    fn handle_chat(&mut self, id: u64, text: String) {
+        let (name, address, role) = match self.connections.iter().find(|client| client.id == id) {
+            Some(client) => (client.chat_name(), client.address, client.role),
+            None => return,
+        };
+
+        if let Some(command_text) = text.strip_prefix('/') {
+            // Only the command itself, passwords stay out of the log
+            let command = text.split_whitespace().next().unwrap_or("");
+            info!(client = id, name = name, address = address; "Chat command {}", command);
+            match ChatCommand::parse(command_text) {
+                Ok(command) if command.required_role() > role => {
+                    self.send_notice(id, "Only admins can do that, /login first");
+                }
+                Ok(command) => self.run_chat_command(id, name, role, command),
+                Err(e) => self.send_notice(id, &e),
+            }
+            return;
+        }
+
+        info!(client = id, name = name, address = address; "Chat: {}", text);
+        let message = ServerMessage::Chat {
+            sender: Some(id),
+            name,
+            channel: ChatChannel::All,
+            time: bans::unix_time(),
+            text,
+        };
+        send_chat(&mut self.connections, id, &message, |_| true);
+    }
+
+    fn run_chat_command(&mut self, id: u64, name: String, role: Role, command: ChatCommand) {
+        let not_found = "No player with that name";
+        match command {
+            ChatCommand::Help => {
+                for (line, required) in chat_commands::HELP {
+                    if *required <= role {
+                        self.send_notice(id, line);
+                    }
+                }
+            }
+            ChatCommand::Whisper { rest } => match self.find_client_by_name(&rest) {
+                Some((_, _, text)) if text.is_empty() => {
+                    self.send_notice(id, "Usage: /w <name> <message>");
+                }
+                Some((target, target_name, text)) => {
+                    let message = ServerMessage::Chat {
+                        sender: Some(id),
+                        name,
+                        channel: ChatChannel::Whisper { to: target_name },
+                        time: bans::unix_time(),
+                        text,
+                    };
+                    send_chat(&mut self.connections, id, &message, |c| c == id || c == target);
+                }
+                None => self.send_notice(id, not_found),
+            },
+            ChatCommand::Team { text } => {
+                let state = &self.state;
+                let team_of = |id| {
+                    state.get_player_by_id(id)
+                        .or(state.get_dead_player_by_id(id).map(|dead| &dead.player))
+                        .and_then(|player| player.team)
+                };
+                let team = match team_of(id) {
+                    Some(team) => team,
+                    None => return self.send_notice(id, "You are not on a team"),
+                };
+                let teammates: Vec<_> = self.connections.iter()
+                    .map(|client| client.id)
+                    .filter(|c| team_of(*c) == Some(team))
+                    .collect();
+                let message = ServerMessage::Chat {
+                    sender: Some(id),
+                    name,
+                    channel: ChatChannel::Team,
+                    time: bans::unix_time(),
+                    text,
+                };
+                send_chat(&mut self.connections, id, &message, |c| teammates.contains(&c));
+            }
+            ChatCommand::Ignore { name: target_name } => {
+                let (target, target_name) = match self.find_client_by_name(&target_name) {
+                    Some((target, _, _)) if target == id => {
+                        return self.send_notice(id, "You can't ignore yourself");
+                    }
+                    Some((target, target_name, _)) => (target, target_name),
+                    None => return self.send_notice(id, not_found),
+                };
+                let client = self.connections.iter_mut().find(|client| client.id == id).unwrap();
+                let reply = if client.ignored.remove(&target) {
+                    format!("No longer ignoring {}", target_name)
+                } else {
+                    client.ignored.insert(target);
+                    format!("Ignoring {}, /ignore them again to stop", target_name)
+                };
+                self.send_notice(id, &reply);
+            }
+            ChatCommand::Stats { name: stats_name } => {
+                let stats_name = stats_name.unwrap_or(name);
+                let reply = match self.stats_store.get(&stats_name) {
+                    Some(stats) => format!(
+                        "{}: {} kills, {} deaths, {} assists, K/D {:.2}, {} rounds won of {}",
+                        stats_name, stats.kills, stats.deaths, stats.assists,
+                        stats.kill_death_ratio(), stats.rounds_won, stats.rounds_played
+                    ),
+                    None => format!("No stats for {}", stats_name),
+                };
+                self.send_notice(id, &reply);
+            }
+            ChatCommand::Ping => {
+                // The client times how long the answer takes
+                if let Some(client) = self.connections.iter_mut().find(|client| client.id == id) {
+                    let _ = send_server_message(
+                        &ServerMessage::Pong(0), &mut client.message_reader.stream
+                    );
+                }
+            }
+            ChatCommand::Vote(VoteCommand::Cast(yes)) => {
+                if self.votes.cast(id, yes) {
+                    self.update_votes(true);
+                } else {
+                    self.send_notice(id, "There is no vote running");
+                }
+            }
+            ChatCommand::Vote(command) => {
+                let kind = match command {
+                    VoteCommand::Kick { name: target_name } => {
+                        match self.find_client_by_name(&target_name) {
+                            Some((target, _, _)) if target == id => {
+                                return self.send_notice(id, "You can't vote to kick yourself");
+                            }
+                            Some((target, target_name, _)) => {
+                                VoteKind::Kick { id: target, name: target_name }
+                            }
+                            None => return self.send_notice(id, not_found),
+                        }
+                    }
+                    VoteCommand::Mode(mode) => VoteKind::Mode(mode),
+                    VoteCommand::Restart => VoteKind::RestartRound,
+                    VoteCommand::Bots(count) => VoteKind::Bots(count),
+                    VoteCommand::Cast(_) => unreachable!(),
+                };
+                self.start_vote(id, name, kind);
+            }
+            ChatCommand::Login { password } => {
+                let client = self.connections.iter_mut().find(|client| client.id == id).unwrap();
+                let address = client.address;
+                if self.admin_password.as_ref() == Some(&password) {
+                    client.role = Role::Admin;
+                    info!(client = id, name = name, address = address; "Admin logged in");
+                    self.send_notice(id, "Logged in as admin, see /help");
+                } else {
+                    warn!(client = id, name = name, address = address; "Failed admin login");
+                    self.send_notice(id, "Wrong password");
+                }
+            }
+            ChatCommand::Kick { rest } => match self.find_client_by_name(&rest) {
+                Some((target, target_name, reason)) => {
+                    self.kick_client(target, &reason, &format!("admin {}", name));
+                    self.send_notice(id, &format!("Kicked {}", target_name));
+                }
+                None => self.send_notice(id, not_found),
+            },
+            ChatCommand::Ban { rest } => match self.find_client_by_name(&rest) {
+                Some((_, target_name, rest)) => {
+                    let (duration, reason) = match chat_commands::parse_ban(&rest) {
+                        Ok(parsed) => parsed,
+                        Err(e) => return self.send_notice(id, &e),
+                    };
+                    self.ban(
+                        BanKind::Name, target_name.clone(), duration, &reason,
+                        &format!("admin {}", name)
+                    );
+                    self.send_notice(id, &format!("Banned {}", target_name));
+                }
+                None => self.send_notice(id, not_found),
+            },
+        }
+    }
+
+    fn start_vote(&mut self, id: u64, name: String, kind: VoteKind) {
+        let address = match self.connections.iter().find(|client| client.id == id) {
+            Some(client) => client.address,
+            None => return,
+        };
+        if !self.playing_clients().contains(&id) {
+            return self.send_notice(id, "Join the game to start a vote");
+        }
+        let description = kind.description();
+        match self.votes.start(kind, id, name.clone(), address.ip()) {
+            Ok(()) => {
+                info!(client = id, name = name, address = address; "Vote started: {}", description);
+                self.announce(&format!(
+                    "{} started a vote: {}. F1 votes yes, F2 votes no", name, description
+                ));
+                self.update_votes(true);
+            }
+            Err(e) => self.send_notice(id, &e),
+        }
+    }
+
+    /**
+     * Ends the running vote when it is decided, and sends it to everyone
+     * when it changed. Only the players with a plane vote.
+     */
+    fn update_votes(&mut self, changed: bool) {
+        let voters = self.playing_clients();
+        let connected: Vec<_> = self.connections.iter().map(|client| client.id).collect();
+        match self.votes.update(&voters, &connected) {
+            VoteUpdate::Unchanged if !changed => return,
+            VoteUpdate::Unchanged | VoteUpdate::Changed => {}
+            VoteUpdate::Passed(kind) => {
+                info!("Vote passed: {}", kind.description());
+                self.announce(&format!("Vote passed: {}", kind.description()));
+                self.apply_vote(kind);
+            }
+            VoteUpdate::Failed(kind) => {
+                info!("Vote failed: {}", kind.description());
+                self.announce(&format!("Vote failed: {}", kind.description()));
+            }
+        }
+
+        let voters = self.playing_clients();
+        for client in &mut self.connections {
+            let status = self.votes.status(&voters, client.id);
+            let _ = send_server_message(
+                &ServerMessage::Vote(status), &mut client.message_reader.stream
+            );
+        }
+    }
+
+    fn apply_vote(&mut self, kind: VoteKind) {
+        match kind {
+            VoteKind::Kick { id, .. } => {
+                self.kick_client(id, "Kicked by vote", "vote");
+            }
+            VoteKind::Mode(name) => {
+                self.config.mode = name;
+                let mode = create_mode(&self.config, &self.stats_store);
+                let teams = self.config.teams.clone();
+                log_op(&mut self.input_log, StateOp::ChangeMode {
+                    mode: mode.clone(), teams: teams.clone()
+                });
+                self.state.change_mode(mode, teams);
+                if let Some(recorder) = &mut self.recorder {
+                    recorder.set_mode(name);
+                }
+                info!("Playing {}", name.name().to_lowercase());
+            }
+            VoteKind::RestartRound => {
+                log_op(&mut self.input_log, StateOp::RestartRound);
+                self.state.restart_round();
+            }
+            VoteKind::Bots(count) => self.bot_config.count = count,
+        }
+    }
+
     fn update_clients(
         &mut self, delta_time: f32,
         hit_players: &[u64],
         hit_powerup_positions: &[(u64, Vec2)],
         fired_laser_positions: &[Vec2],
-    ) {
+    ) -> Vec<(SoundEffect, Vec2)> {
         // Send data to clients
         let mut clients_to_delete = vec!();
-        let mut sounds_to_play = vec!();
+        let mut banned_clients = vec!();
+        let mut chat_messages = vec!();
+        let mut ballots = vec!();
+        let mut sounds_to_play = self.update_bots(delta_time);
 
         macro_rules! remove_player_on_disconnect {
-            ($op:expr, $id:expr) => {
+            ($op:expr, $client:expr) => {
                 match $op {
                     Ok(_) => {},
                     Err(e) => {
                         match e.kind() {
                             io::ErrorKind::ConnectionReset | io::ErrorKind::BrokenPipe => {
-                                println!("Player {} disconnected", $id);
-                                clients_to_delete.push($id);
+                                info!(
+                                    client = $client.id,
+                                    name = $client.name.as_deref().unwrap_or(""),
+                                    address = $client.address;
+                                    "Player disconnected"
+                                );
+                                clients_to_delete.push($client.id);
                                 break;
                             }
                             e => {
@@ -189,40 +1086,94 @@ impl Server {
         }
 
         for client in self.connections.iter_mut() {
-            remove_player_on_disconnect!(client.message_reader.fetch_bytes(), client.id);
+            let received = client.message_reader.fetch_bytes();
+            if let Ok(bytes) = received {
+                metrics::count_received(bytes);
+            }
+            remove_player_on_disconnect!(received, client);
 
+            let mut replies = vec!();
             for message in client.message_reader.iter() {
                 match bincode::deserialize(&message) {
                     Ok(ClientMessage::Input(input)) => {
                         client.input = input;
                     },
                     Ok(ClientMessage::JoinGame{ mut name, plane, color }) => {
-                        let mut random = rand::thread_rng();
                         if name.trim().len() != 0 {
                             name = name.trim().unicode_truncate(20).0.to_string()
                         } else {
                             name = "Mr Whitespace".into();
                         }
 
-                        let player = Player::new(
-                            client.id,
-                            vec2(
-                                random.gen_range(0., constants::WORLD_SIZE),
-                                random.gen_range(0., constants::WORLD_SIZE)
-                            ),
-                            plane,
-                            color,
-                            name
+                        if let Some(ban) = self.bans.find_name_ban(&name) {
+                            banned_clients.push((client.id, ban.reason.clone()));
+                            continue;
+                        }
+                        client.name = Some(name.clone());
+                        client.spectating = false;
+
+                        log_op(&mut self.input_log, StateOp::Join {
+                            id: client.id, name: name.clone(), plane, color: color.clone()
+                        });
+                        self.state.join(client.id, name, plane, color);
+                    },
+                    Ok(ClientMessage::Spectate) => {
+                        info!(
+                            client = client.id,
+                            name = client.name.as_deref().unwrap_or(""),
+                            address = client.address;
+                            "Client is spectating"
                         );
-                        self.state.add_player(player);
+                        client.spectating = true;
+                        self.state.remove_player(client.id);
+                        log_op(&mut self.input_log, StateOp::Leave { id: client.id });
+                    },
+                    Ok(ClientMessage::Respawn { plane }) => {
+                        self.state.respawn_player(client.id, plane);
+                        log_op(&mut self.input_log, StateOp::Respawn { id: client.id, plane });
+                    },
+                    Ok(ClientMessage::Chat(text)) => {
+                        let text = match clean_chat_message(&text) {
+                            Some(text) => text,
+                            None => continue,
+                        };
+                        if !client.chat_limit.allow() {
+                            replies.push(notice("You are sending messages too fast"));
+                            continue;
+                        }
+                        chat_messages.push((client.id, text));
+                    },
+                    Ok(ClientMessage::Vote(yes)) => {
+                        ballots.push((client.id, yes));
+                    },
+                    Ok(ClientMessage::Ping(number)) => {
+                        replies.push(ServerMessage::Pong(number));
+                    },
+                    Ok(ClientMessage::RequestLifetimeStats) => {
+                        let stats_store = &self.stats_store;
+                        let mine = client.name.as_ref()
+                            .and_then(|name| stats_store.get(name))
+                            .cloned();
+                        replies.push(ServerMessage::LifetimeStats {
+                            top: stats_store.top(constants::LIFETIME_STATS_COUNT),
+                            mine,
+                        });
                     },
                     Err(_) => {
-                        println!("Could not decode message from {}, deleting", client.id);
+                        warn!(
+                            client = client.id, address = client.address;
+                            "Could not decode message, deleting client"
+                        );
                         clients_to_delete.push(client.id);
                     }
                 }
             }
 
+            for reply in &replies {
+                let result = send_server_message(reply, &mut client.message_reader.stream);
+                remove_player_on_disconnect!(result, client);
+            }
+
             for position in fired_laser_positions {
                 sounds_to_play.push((SoundEffect::LaserFire, *position));
             }
@@ -233,54 +1184,33 @@ impl Server {
                     &ServerMessage::PlayerHit(*hit_id),
                     &mut client.message_reader.stream
                 );
-                remove_player_on_disconnect!(result, client.id);
+                remove_player_on_disconnect!(result, client);
             }
 
-            let mut bullet = None;
-            for player in &mut self.state.players {
-                if player.id == client.id {
-                    player.update(
-                        client.input.x_input,
-                        client.input.y_input,
-                        &self.state.hurricane,
-                        delta_time,
-                    );
-
-                    if client.input.shooting {
-                        let (b, start_charging_laser) = player.shoot();
-                        bullet = b;
-                        if start_charging_laser {
-                            sounds_to_play.push((SoundEffect::LaserCharge, player.position));
-                        }
-                    }
-
-                    if client.input.activating_powerup {
-                        player.trigger_powerup_if_available();
-                    }
-
-                    if player.health <= 0 {
-                        let result = send_server_message(
-                            &ServerMessage::YouDied,
-                            &mut client.message_reader.stream
-                        );
-                        remove_player_on_disconnect!(result, client.id);
-                        sounds_to_play.push((SoundEffect::Explosion, player.position));
-                    }
-                    break
-                }
+            if let Some(input_log) = &mut self.input_log {
+                input_log.fly(client.id, &client.input);
+            }
+            let bullet = self.state.apply_input(
+                client.id, &client.input, delta_time, &mut sounds_to_play
+            );
+            // Fired before anything that can drop the client, the input log
+            // fires it together with the input
+            if let Some(bullet) = bullet {
+                self.state.fire(client.id, bullet, &mut sounds_to_play);
+            }
+            if self.state.get_player_by_id(client.id).map(|p| p.health <= 0).unwrap_or(false) {
+                let result = send_server_message(
+                    &ServerMessage::YouDied,
+                    &mut client.message_reader.stream
+                );
+                remove_player_on_disconnect!(result, client);
             }
 
             let result = send_server_message(
-                &ServerMessage::GameState(self.state.clone()),
+                &ServerMessage::GameState(Box::new(self.state.clone())),
                 &mut client.message_reader.stream
             );
-            remove_player_on_disconnect!(result, client.id);
-
-            if let Some(bullet) = bullet {
-                let pos = bullet.get_position();
-                self.state.add_bullet(bullet);
-                sounds_to_play.push((SoundEffect::Gun, pos));
-            }
+            remove_player_on_disconnect!(result, client);
 
             // play powerup sound effects
             for (player_id, position) in hit_powerup_positions {
@@ -289,7 +1219,7 @@ impl Server {
                         &ServerMessage::PlaySound(SoundEffect::Powerup, *position),
                         &mut client.message_reader.stream
                     );
-                    remove_player_on_disconnect!(result, client.id);
+                    remove_player_on_disconnect!(result, client);
                 }
             }
         }
@@ -300,28 +1230,106 @@ impl Server {
                     &ServerMessage::PlaySound(*sound, *pos),
                     &mut client.message_reader.stream
                 );
-                remove_player_on_disconnect!(result, client.id);
+                remove_player_on_disconnect!(result, client);
             }
         }
 
-        let dead_players: Vec<_> = self.state.players.iter()
-            .filter(|player| player.health <= 0).map(|player| player.id)
-            .collect();
+        for (id, yes) in ballots {
+            if self.votes.cast(id, yes) {
+                self.update_votes(true);
+            }
+        }
+        for (id, text) in chat_messages {
+            self.handle_chat(id, text);
+        }
 
-        self.state.players.retain(
-            |player| !clients_to_delete.contains(&player.id) &&
-                !dead_players.contains(&player.id)
-        );
+        self.state.remove_dead_players();
+        log_op(&mut self.input_log, StateOp::RemoveDeadPlayers);
         self.connections.retain(
             |client| !clients_to_delete.contains(&client.id)
         );
+        for id in &clients_to_delete {
+            self.state.remove_player(*id);
+            log_op(&mut self.input_log, StateOp::Leave { id: *id });
+        }
+
+        for (id, reason) in banned_clients {
+            info!(client = id; "Disconnecting client who joined with a banned name");
+            self.disconnect_client(id, &reason);
+        }
+
+        sounds_to_play
+    }
+}
+
+/**
+ * Adds a change of the game state to the input log, if inputs are logged
+ */
+fn log_op(input_log: &mut Option<InputLog>, op: StateOp) {
+    if let Some(input_log) = input_log {
+        input_log.push(op);
+    }
+}
+
+fn create_mode(config: &ServerConfig, stats_store: &StatsStore) -> GameModeKind {
+    match config.mode {
+        ModeName::FreeForAll => FreeForAll.into(),
+        ModeName::CaptureTheFlag => CaptureTheFlag::new(&config.ctf).into(),
+        ModeName::KingOfTheHill => KingOfTheHill::new(&config.koth).into(),
+        ModeName::BattleRoyale => BattleRoyale::new(&config.battle_royale).into(),
+        ModeName::Race => {
+            let mut race = Race::new(&config.race);
+            race.set_records(stats_store.lap_records());
+            race.into()
+        }
+    }
+}
+
+/**
+ * Plays an input log again and reports the first tick where the game went
+ * differently than when it was logged
+ */
+fn verify_input_log(path: &str) -> Result<(), String> {
+    let file = std::fs::File::open(path)
+        .map_err(|e| format!("Could not open {}: {}", path, e))?;
+    let verification = inputlog::verify(io::BufReader::new(file))
+        .map_err(|e| format!("Could not read {}: {}", path, e))?;
+    match verification {
+        Verification::Matches { ticks } => {
+            println!("All {} ticks of {} played out the same way", ticks, path);
+            Ok(())
+        }
+        Verification::Diverged { tick, difference } => {
+            Err(format!("{} diverged at tick {}: {}", path, tick, difference))
+        }
     }
 }
 
 fn main() {
-    let mut server = Server::new();
-    loop {
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(|arg| arg == "--verify").unwrap_or(false) {
+        let path = args.get(2).expect("Usage: server --verify <input log>");
+        if let Err(e) = verify_input_log(path) {
+            println!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let config = ServerConfig::load().expect("Could not read server config");
+    log::init(&config.log);
+    let mut server = Server::new(config);
+
+    // Stop between ticks on ctrl-c, so that dropping the server saves the
+    // stats that are not written yet
+    let stop = Arc::new(AtomicBool::new(false));
+    for signal in &[signal_hook::consts::SIGINT, signal_hook::consts::SIGTERM] {
+        signal_hook::flag::register(*signal, Arc::clone(&stop))
+            .expect("Could not handle signals");
+    }
+    while !stop.load(Ordering::Relaxed) {
         server.update();
     }
+    info!("Shutting down");
 }
 
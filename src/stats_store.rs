@@ -0,0 +1,150 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use libplen::player::PlaneType;
+use libplen::race::LapRecord;
+use libplen::scoreboard::{Scoreboard, Weapon};
+use libplen::stats::LifetimeStats;
+use libplen::error;
+
+use crate::bans::unix_time;
+
+// Kills and laps are saved at most this often, round results right away and
+// everything else when the server stops
+const SAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/**
+ * Lifetime statistics of all players keyed by name, stored as a json file
+ */
+pub struct StatsStore {
+    path: PathBuf,
+    players: HashMap<String, LifetimeStats>,
+    // Whether there are changes that are not saved yet
+    dirty: bool,
+    last_save: Instant,
+}
+
+impl StatsStore {
+    pub fn load(path: PathBuf) -> io::Result<Self> {
+        let players = match fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e),
+        };
+        Ok(Self { path, players, dirty: false, last_save: Instant::now() })
+    }
+
+    /**
+     * Saves the changes once the save interval has passed since the last
+     * save, called every tick
+     */
+    pub fn save_if_due(&mut self) {
+        if self.dirty && self.last_save.elapsed() >= SAVE_INTERVAL {
+            self.save();
+        }
+    }
+
+    fn save(&mut self) {
+        self.dirty = false;
+        self.last_save = Instant::now();
+        let result = serde_json::to_string_pretty(&self.players)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+            .and_then(|content| {
+                // Write to a temporary file first so a crash can't leave a
+                // half written stats file behind
+                let tmp_path = self.path.with_extension("tmp");
+                fs::write(&tmp_path, content)?;
+                fs::rename(&tmp_path, &self.path)
+            });
+        if let Err(e) = result {
+            error!("Could not save stats to {}: {}", self.path.display(), e);
+        }
+    }
+
+    fn entry(&mut self, name: &str) -> &mut LifetimeStats {
+        self.players.entry(name.to_string()).or_default()
+    }
+
+    /**
+     * Records a death, the killer is None for suicides. Players without
+     * stats, like bots, are None as well.
+     */
+    pub fn record_death(
+        &mut self,
+        killer: Option<(&str, PlaneType)>,
+        victim: Option<&str>,
+        weapon: Weapon
+    ) {
+        let now = unix_time();
+        if let Some((killer, plane)) = killer {
+            self.entry(killer).record_kill(plane, weapon, now);
+        }
+        if let Some(victim) = victim {
+            self.entry(victim).record_death(now);
+        }
+        self.dirty = true;
+    }
+
+    /**
+     * Adds the stats of a round for everyone except the ignored players
+     */
+    pub fn record_round(&mut self, scoreboard: &Scoreboard, winners: &[u64], ignored: &[u64]) {
+        let now = unix_time();
+        for (id, stats) in scoreboard.stats.iter().filter(|(id, _)| !ignored.contains(id)) {
+            self.entry(&stats.name).record_round(stats, winners.contains(id), now);
+        }
+        self.save();
+    }
+
+    pub fn record_lap(&mut self, name: &str, plane: PlaneType, time: f32) {
+        if self.entry(name).record_lap(plane, time) {
+            self.dirty = true;
+        }
+    }
+
+    /**
+     * The fastest lap of all players in every plane
+     */
+    pub fn lap_records(&self) -> BTreeMap<PlaneType, LapRecord> {
+        let mut records: BTreeMap<PlaneType, LapRecord> = BTreeMap::new();
+        for (name, stats) in &self.players {
+            for (plane, time) in &stats.best_laps {
+                let is_best = records.get(plane).map(|r| *time < r.time).unwrap_or(true);
+                if is_best {
+                    records.insert(*plane, LapRecord { name: name.clone(), time: *time });
+                }
+            }
+        }
+        records
+    }
+
+    pub fn get(&self, name: &str) -> Option<&LifetimeStats> {
+        self.players.get(name)
+    }
+
+    /**
+     * The players with the most kills, best first
+     */
+    pub fn top(&self, count: usize) -> Vec<(String, LifetimeStats)> {
+        let mut players: Vec<_> = self.players.iter()
+            .map(|(name, stats)| (name.clone(), stats.clone()))
+            .collect();
+        players.sort_by(|(name_a, a), (name_b, b)| {
+            b.kills.cmp(&a.kills).then(name_a.cmp(name_b))
+        });
+        players.truncate(count);
+        players
+    }
+}
+
+impl Drop for StatsStore {
+    fn drop(&mut self) {
+        if self.dirty {
+            self.save();
+        }
+    }
+}
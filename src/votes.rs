@@ -0,0 +1,233 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+use serde_derive::Deserialize;
+
+use libplen::gamemode::ModeName;
+use libplen::messages::VoteStatus;
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct VoteConfig {
+    pub enabled: bool,
+    // Share of the players that have to vote yes, more than this passes
+    pub threshold: f32,
+    pub kick_threshold: f32,
+    // Kicks need at least this many yes votes, so that one player can't
+    // kick the only other one
+    pub min_kick_votes: usize,
+    // Seconds a vote stays open
+    pub duration: u64,
+    // Seconds before a player can start another vote
+    pub cooldown: u64,
+    // Votes can't fill the game with more bots than this
+    pub max_bots: usize,
+}
+
+impl Default for VoteConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            threshold: 0.5,
+            kick_threshold: 0.6,
+            min_kick_votes: 2,
+            duration: 30,
+            cooldown: 120,
+            max_bots: 8,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub enum VoteKind {
+    Kick { id: u64, name: String },
+    Mode(ModeName),
+    RestartRound,
+    // Bots fill the game up to this many players
+    Bots(usize),
+}
+
+impl VoteKind {
+    pub fn description(&self) -> String {
+        match self {
+            VoteKind::Kick { name, .. } => format!("Kick {}", name),
+            VoteKind::Mode(mode) => format!("Play {}", mode.name().to_lowercase()),
+            VoteKind::RestartRound => String::from("Restart the round"),
+            VoteKind::Bots(0) => String::from("Remove the bots"),
+            VoteKind::Bots(count) => format!("Fill up with bots to {} players", count),
+        }
+    }
+}
+
+struct Vote {
+    kind: VoteKind,
+    initiator: String,
+    ballots: HashMap<u64, bool>,
+    started: Instant,
+}
+
+pub enum VoteUpdate {
+    Unchanged,
+    // The number of voters changed, so the votes needed did too
+    Changed,
+    Passed(VoteKind),
+    Failed(VoteKind),
+}
+
+/**
+ * The running vote, and when each address last started one
+ */
+pub struct Votes {
+    config: VoteConfig,
+    current: Option<Vote>,
+    last_started: HashMap<IpAddr, Instant>,
+    // Voters at the last update
+    voters: usize,
+}
+
+impl Votes {
+    pub fn new(config: VoteConfig) -> Self {
+        Self {
+            config,
+            current: None,
+            last_started: HashMap::new(),
+            voters: 0,
+        }
+    }
+
+    /**
+     * Starts a vote with a yes from the player who started it, unless
+     * another one is running or they started one too recently
+     */
+    pub fn start(&mut self, kind: VoteKind, initiator: u64, name: String, address: IpAddr)
+        -> Result<(), String>
+    {
+        if !self.config.enabled {
+            return Err(String::from("Voting is turned off on this server"));
+        }
+        if self.current.is_some() {
+            return Err(String::from("Wait for the running vote to end"));
+        }
+        if let VoteKind::Bots(count) = kind {
+            if count > self.config.max_bots {
+                return Err(format!("At most {} bots can be voted for", self.config.max_bots));
+            }
+        }
+        let cooldown = Duration::from_secs(self.config.cooldown);
+        if let Some(elapsed) = self.last_started.get(&address).map(Instant::elapsed) {
+            if elapsed < cooldown {
+                return Err(format!(
+                    "You can start another vote in {} seconds",
+                    (cooldown - elapsed).as_secs() + 1
+                ));
+            }
+        }
+
+        self.last_started.insert(address, Instant::now());
+        let mut ballots = HashMap::new();
+        ballots.insert(initiator, true);
+        self.current = Some(Vote { kind, initiator: name, ballots, started: Instant::now() });
+        Ok(())
+    }
+
+    /**
+     * Records or changes a vote. Returns false if there is nothing to vote
+     * on.
+     */
+    pub fn cast(&mut self, id: u64, yes: bool) -> bool {
+        match &mut self.current {
+            Some(vote) => {
+                vote.ballots.insert(id, yes);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /**
+     * The clients that get to vote, the player a kick vote is about
+     * doesn't
+     */
+    fn voters<'a>(vote: &'a Vote, clients: &'a [u64]) -> impl Iterator<Item = &'a u64> {
+        clients.iter().filter(move |id| match vote.kind {
+            VoteKind::Kick { id: target, .. } => **id != target,
+            _ => true,
+        })
+    }
+
+    fn needed(&self, vote: &Vote, voters: usize) -> usize {
+        let (threshold, minimum) = match vote.kind {
+            VoteKind::Kick { .. } => (self.config.kick_threshold, self.config.min_kick_votes),
+            _ => (self.config.threshold, 1),
+        };
+        let needed = ((threshold * voters as f32).floor() as usize + 1).min(voters);
+        needed.max(minimum)
+    }
+
+    fn count(vote: &Vote, clients: &[u64]) -> (usize, usize, usize) {
+        let mut yes = 0;
+        let mut no = 0;
+        let mut voters = 0;
+        for id in Self::voters(vote, clients) {
+            voters += 1;
+            match vote.ballots.get(id) {
+                Some(true) => yes += 1,
+                Some(false) => no += 1,
+                None => {}
+            }
+        }
+        (yes, no, voters)
+    }
+
+    /**
+     * Ends the vote once enough players voted yes, once it can't pass any
+     * more or when the time is up. Only the clients in the game vote, and a
+     * kick fails if its target disconnects.
+     */
+    pub fn update(&mut self, clients: &[u64], connected: &[u64]) -> VoteUpdate {
+        let vote = match &self.current {
+            Some(vote) => vote,
+            None => return VoteUpdate::Unchanged,
+        };
+        let (yes, no, voters) = Self::count(vote, clients);
+        let needed = self.needed(vote, voters);
+        let target_left = match vote.kind {
+            VoteKind::Kick { id, .. } => !connected.contains(&id),
+            _ => false,
+        };
+        let timed_out = vote.started.elapsed() > Duration::from_secs(self.config.duration);
+
+        if yes >= needed && !target_left {
+            VoteUpdate::Passed(self.current.take().unwrap().kind)
+        } else if target_left || timed_out || voters - no < needed {
+            VoteUpdate::Failed(self.current.take().unwrap().kind)
+        } else if voters != self.voters {
+            self.voters = voters;
+            VoteUpdate::Changed
+        } else {
+            VoteUpdate::Unchanged
+        }
+    }
+
+    /**
+     * The running vote as the client sees it
+     */
+    pub fn status(&self, clients: &[u64], recipient: u64) -> Option<VoteStatus> {
+        self.current.as_ref().map(|vote| {
+            let (yes, no, voters) = Self::count(vote, clients);
+            let duration = Duration::from_secs(self.config.duration);
+            VoteStatus {
+                description: vote.kind.description(),
+                initiator: vote.initiator.clone(),
+                yes,
+                no,
+                needed: self.needed(vote, voters),
+                time_left: duration.checked_sub(vote.started.elapsed())
+                    .unwrap_or_default()
+                    .as_secs_f32(),
+                voted: vote.ballots.get(&recipient).cloned(),
+            }
+        })
+    }
+}